@@ -0,0 +1,93 @@
+use log::warn;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::error::UpdateError;
+
+/// Exponential backoff with jitter for transient update failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Backoff {
+    /// Total attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for every further retry.
+    pub base_delay: Duration,
+    /// Upper bound for a single delay.
+    pub max_delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            max_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A random number in `0..=max`. Jitter doesn't need a real RNG; the
+/// std hasher is randomly seeded per instance.
+pub(crate) fn random_up_to(max: u64) -> u64 {
+    if max == 0 {
+        return 0;
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(max);
+    hasher.finish() % (max + 1)
+}
+
+impl Backoff {
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Backoff {
+            max_attempts,
+            base_delay,
+            max_delay,
+        }
+    }
+
+    /// Delay before retry number `retry` (1-based): `base * 2^(retry-1)`,
+    /// capped at `max_delay`, with "equal jitter" (half fixed, half random)
+    /// so hosts failing together don't retry in lockstep.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let exp = self
+            .base_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+            .as_millis() as u64;
+        Duration::from_millis(exp / 2 + random_up_to(exp / 2))
+    }
+
+    /// Run `op` until it succeeds, fails with a non-transient error, or
+    /// `max_attempts` is used up. `what` names the operation in log lines.
+    pub async fn retry<T, F, Fut>(&self, what: &str, mut op: F) -> Result<T, UpdateError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, UpdateError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(e) if e.class().is_transient() && attempt < self.max_attempts => {
+                    let delay = self.delay(attempt);
+                    warn!(
+                        "Transient failure {} (attempt {}/{}): {}; retrying in {}ms",
+                        what,
+                        attempt,
+                        self.max_attempts,
+                        e,
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::error::UpdateError;
+use crate::response::NamecheapResponse;
+
+/// What kind of problem a failed update ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorClass {
+    /// Wrong Dynamic DNS password.
+    Auth,
+    /// The domain is not in the account or Dynamic DNS is disabled for it.
+    UnknownDomain,
+    /// The host name is not valid for this domain.
+    UnknownHost,
+    /// The host exists but has no A record to update.
+    RecordMissing,
+    /// Namecheap is throttling us.
+    RateLimited,
+    /// Namecheap-side failure (5xx, unexpected error messages).
+    ServerError,
+    /// Network problem between us and Namecheap.
+    Network,
+    /// Anything not in the catalog.
+    Unknown,
+}
+
+/// When a failed host should be tried again for the same IP. A new IP is
+/// always pushed right away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryPolicy {
+    /// Try again on the next cycle.
+    NextCycle,
+    /// Leave the host alone for this long; retrying sooner won't help.
+    After(Duration),
+}
+
+impl ErrorClass {
+    /// What the operator should do about it.
+    pub fn hint(self) -> &'static str {
+        match self {
+            ErrorClass::Auth => {
+                "Check the Dynamic DNS password under Domain List → Manage → Advanced DNS \
+                 (it is not your Namecheap account password)."
+            }
+            ErrorClass::UnknownDomain => {
+                "Check the domain is in this Namecheap account, uses Namecheap DNS \
+                 and has Dynamic DNS enabled."
+            }
+            ErrorClass::UnknownHost => {
+                "Check the host name; use @ for the bare domain and * for the wildcard record."
+            }
+            ErrorClass::RecordMissing => {
+                "Create an A + Dynamic DNS Record for this host under Advanced DNS."
+            }
+            ErrorClass::RateLimited => {
+                "Namecheap is rate limiting updates; consider a longer interval."
+            }
+            ErrorClass::ServerError => "Namecheap-side problem; it will be retried.",
+            ErrorClass::Network => "Could not reach Namecheap; it will be retried.",
+            ErrorClass::Unknown => "Unrecognised Namecheap error; check domain/host/password.",
+        }
+    }
+
+    /// Worth retrying right away with backoff: timeouts, connection resets,
+    /// 5xx. Everything else would just hammer Namecheap.
+    pub fn is_transient(self) -> bool {
+        matches!(self, ErrorClass::Network | ErrorClass::ServerError)
+    }
+
+    pub fn retry_policy(self) -> RetryPolicy {
+        match self {
+            ErrorClass::Auth | ErrorClass::UnknownDomain | ErrorClass::UnknownHost => {
+                RetryPolicy::After(Duration::from_secs(60 * 60))
+            }
+            ErrorClass::RecordMissing => RetryPolicy::After(Duration::from_secs(30 * 60)),
+            ErrorClass::RateLimited => RetryPolicy::After(Duration::from_secs(15 * 60)),
+            ErrorClass::ServerError | ErrorClass::Network | ErrorClass::Unknown => {
+                RetryPolicy::NextCycle
+            }
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorClass::Auth => "auth",
+            ErrorClass::UnknownDomain => "unknown domain",
+            ErrorClass::UnknownHost => "unknown host",
+            ErrorClass::RecordMissing => "record missing",
+            ErrorClass::RateLimited => "rate limited",
+            ErrorClass::ServerError => "server error",
+            ErrorClass::Network => "network",
+            ErrorClass::Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A known Namecheap DDNS failure, matched by `<ResponseNumber>` or by a
+/// lowercase fragment of its message.
+struct KnownError {
+    code: Option<u32>,
+    fragment: &'static str,
+    class: ErrorClass,
+}
+
+const CATALOG: &[KnownError] = &[
+    KnownError {
+        code: Some(304156),
+        fragment: "passwords do not match",
+        class: ErrorClass::Auth,
+    },
+    KnownError {
+        code: Some(316153),
+        fragment: "domain name not found",
+        class: ErrorClass::UnknownDomain,
+    },
+    KnownError {
+        code: None,
+        fragment: "domain name(s)",
+        class: ErrorClass::UnknownDomain,
+    },
+    KnownError {
+        code: None,
+        fragment: "a record not found",
+        class: ErrorClass::RecordMissing,
+    },
+    KnownError {
+        code: None,
+        fragment: "no records updated",
+        class: ErrorClass::RecordMissing,
+    },
+    KnownError {
+        code: None,
+        fragment: "invalid host",
+        class: ErrorClass::UnknownHost,
+    },
+    KnownError {
+        code: None,
+        fragment: "host name not found",
+        class: ErrorClass::UnknownHost,
+    },
+    KnownError {
+        code: None,
+        fragment: "too many requests",
+        class: ErrorClass::RateLimited,
+    },
+    KnownError {
+        code: None,
+        fragment: "rate limit",
+        class: ErrorClass::RateLimited,
+    },
+    KnownError {
+        code: None,
+        fragment: "unexpected error",
+        class: ErrorClass::ServerError,
+    },
+    KnownError {
+        code: None,
+        fragment: "server error",
+        class: ErrorClass::ServerError,
+    },
+];
+
+/// Classify a Namecheap error response using the catalog.
+pub fn classify_response(response: &NamecheapResponse) -> ErrorClass {
+    let codes = response.codes();
+    if let Some(known) = CATALOG
+        .iter()
+        .find(|k| k.code.is_some_and(|c| codes.contains(&c)))
+    {
+        return known.class;
+    }
+
+    let messages: Vec<String> = response
+        .messages()
+        .iter()
+        .map(|m| m.to_lowercase())
+        .collect();
+    CATALOG
+        .iter()
+        .find(|k| messages.iter().any(|m| m.contains(k.fragment)))
+        .map(|k| k.class)
+        .unwrap_or(ErrorClass::Unknown)
+}
+
+impl UpdateError {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            UpdateError::Transport(_) => ErrorClass::Network,
+            UpdateError::HttpStatus(status) if status.as_u16() == 429 => ErrorClass::RateLimited,
+            UpdateError::HttpStatus(status) if status.is_server_error() => ErrorClass::ServerError,
+            UpdateError::HttpStatus(_) => ErrorClass::Unknown,
+            UpdateError::Namecheap(response) => classify_response(response),
+            UpdateError::Malformed(_) => ErrorClass::ServerError,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use log::{info, trace};
+use std::time::Duration;
+
+use crate::config::DEFAULT_ENDPOINT;
+use crate::detect::Family;
+use crate::error::UpdateError;
+use crate::response::{parse_namecheap_response, NamecheapResponse};
+
+const USER_AGENT: &str = "namecheap-ddns-rust/0.1";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP client whose connections only use `family`, for querying IP
+/// providers that answer over both IPv4 and IPv6.
+pub fn http_client_for(family: Family) -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(TIMEOUT)
+        .local_address(family.unspecified())
+        .build()
+}
+
+/// Namecheap Dynamic DNS client.
+///
+/// Cheap to clone; clones share the underlying HTTP connection pool.
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    endpoint: String,
+}
+
+impl Client {
+    /// Client talking to Namecheap's public endpoint with the default
+    /// user agent and a 10 second timeout.
+    pub fn new() -> Result<Self, reqwest::Error> {
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(TIMEOUT)
+            .build()?;
+        Ok(Client::with_http_client(http))
+    }
+
+    /// Client using a preconfigured `reqwest::Client` (proxies, timeouts, ...).
+    pub fn with_http_client(http: reqwest::Client) -> Self {
+        Client {
+            http,
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+        }
+    }
+
+    /// Send updates to `endpoint` instead of Namecheap, e.g. a staging or
+    /// mock server.
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// The underlying HTTP client, also used for IP detection.
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    /// Point `host`.`domain` at `ip`. On success returns Namecheap's parsed
+    /// response, whose `ip` is the address actually applied.
+    pub async fn update(
+        &self,
+        host: &str,
+        domain: &str,
+        password: &str,
+        ip: &str,
+    ) -> Result<NamecheapResponse, UpdateError> {
+        let resp = self
+            .http
+            .get(&self.endpoint)
+            .query(&[
+                ("host", host),
+                ("domain", domain),
+                ("password", password),
+                ("ip", ip),
+            ])
+            .send()
+            .await?;
+        let status = resp.status();
+        let body = resp.text().await?;
+
+        if !status.is_success() {
+            trace!("Namecheap full response body: {}", body);
+            return Err(UpdateError::HttpStatus(status));
+        }
+
+        let response = match parse_namecheap_response(&body) {
+            Ok(response) => response,
+            Err(e) => {
+                trace!("Namecheap full response body: {}", body);
+                return Err(e);
+            }
+        };
+
+        if !response.is_success() {
+            // Only dump full XML at trace level so normal logs stay clean
+            trace!("Namecheap full XML error response: {}", body);
+            return Err(UpdateError::Namecheap(Box::new(response)));
+        }
+
+        info!(
+            "Namecheap DDNS update succeeded: host={}, ip={}, status={} {}",
+            host,
+            response
+                .ip
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| ip.to_string()),
+            status.as_u16(),
+            status.canonical_reason().unwrap_or(""),
+        );
+        trace!("Namecheap full XML response: {}", body);
+        Ok(response)
+    }
+}
@@ -0,0 +1,842 @@
+use log::warn;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::backoff::Backoff;
+use crate::detect::{DetectOptions, DetectStrategy, Family, DEFAULT_EXEC_TIMEOUT};
+use crate::provider::Provider;
+use crate::verify::DEFAULT_DNS_SERVERS;
+
+const DEFAULT_INTERVAL_SECS: u64 = 300;
+/// Happy-eyeballs style head start for each provider in race mode.
+const DEFAULT_RACE_STAGGER_MS: u64 = 250;
+const DEFAULT_IP_PROVIDERS: &str =
+    "https://ifconfig.me/ip,https://ipv4.icanhazip.com,https://api.ipify.org";
+const DEFAULT_IP6_PROVIDERS: &str = "https://ipv6.icanhazip.com,https://api6.ipify.org";
+
+/// Namecheap's Dynamic DNS update endpoint.
+pub const DEFAULT_ENDPOINT: &str = "https://dynamicdns.park-your-domain.com/update";
+pub const DEFAULT_STATE_PATH: &str = "/data/state.json";
+
+/// Config file picked up automatically when no `--config`/`NC_CONFIG` is given.
+pub const DEFAULT_CONFIG_PATH: &str = "/data/config.toml";
+
+/// One Namecheap domain with its own DDNS password and hosts.
+#[non_exhaustive]
+pub struct DomainConfig {
+    pub domain: String,
+    pub password: String,
+    pub hosts: Vec<String>,
+    /// Hosts whose AAAA record is also kept in sync with our IPv6.
+    pub aaaa_hosts: Vec<String>,
+    pub interval_secs: u64,
+}
+
+impl DomainConfig {
+    /// A domain updating the A records of `hosts` every
+    /// `DEFAULT_INTERVAL_SECS`.
+    pub fn new(domain: impl Into<String>, password: impl Into<String>, hosts: Vec<String>) -> Self {
+        DomainConfig {
+            domain: domain.into(),
+            password: password.into(),
+            hosts,
+            aaaa_hosts: Vec::new(),
+            interval_secs: DEFAULT_INTERVAL_SECS,
+        }
+    }
+
+    pub fn with_aaaa_hosts(mut self, aaaa_hosts: Vec<String>) -> Self {
+        self.aaaa_hosts = aaaa_hosts;
+        self
+    }
+
+    pub fn with_interval_secs(mut self, interval_secs: u64) -> Self {
+        self.interval_secs = interval_secs;
+        self
+    }
+
+    /// Hosts to update for `family`.
+    pub fn hosts_for(&self, family: Family) -> &[String] {
+        match family {
+            Family::V4 => &self.hosts,
+            Family::V6 => &self.aaaa_hosts,
+        }
+    }
+}
+
+#[non_exhaustive]
+pub struct Config {
+    pub domains: Vec<DomainConfig>,
+    pub ip_providers: Vec<String>,
+    /// IPv6 providers, only queried when some domain has `aaaa_hosts`.
+    pub ip6_providers: Vec<String>,
+    /// DDNS update URL; overridable to point at a staging or mock server.
+    pub endpoint: String,
+    pub state_path: PathBuf,
+    /// Where provider health is persisted, next to the state file; `None`
+    /// keeps it in memory only.
+    pub provider_health_path: Option<PathBuf>,
+    /// Backoff for transient update failures within a cycle.
+    pub retry: Backoff,
+    /// How `ip_providers` are combined and which answers are accepted.
+    pub detect: DetectOptions,
+    /// Same as `detect`, for `ip6_providers`.
+    pub detect6: DetectOptions,
+    /// Check the published records on `dns_servers` rather than trusting
+    /// the state file alone.
+    pub verify_dns: bool,
+    /// Authoritative nameservers (`host[:port]`) for `verify_dns` and
+    /// propagation checks.
+    pub dns_servers: Vec<String>,
+    /// After a successful update, poll `dns_servers` this long for the new
+    /// address to appear; `None` skips the check.
+    pub propagation_timeout: Option<Duration>,
+}
+
+/// Every problem found while loading the configuration, reported together
+/// so a broken deployment can be fixed in one go.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl ConfigError {
+    fn single(problem: String) -> Self {
+        ConfigError {
+            problems: vec![problem],
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration: {}", self.problems.join("; "))
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// On-disk shape of the TOML config file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    interval_seconds: Option<u64>,
+    ip_providers: Option<Vec<String>>,
+    ip6_providers: Option<Vec<String>>,
+    endpoint: Option<String>,
+    state_file: Option<PathBuf>,
+    retry: Option<FileRetry>,
+    detect_mode: Option<String>,
+    #[serde(default)]
+    allow_private_ip: bool,
+    #[serde(default)]
+    persist_provider_health: bool,
+    #[serde(default)]
+    verify_dns: bool,
+    dns_servers: Option<Vec<String>>,
+    propagation_timeout_seconds: Option<u64>,
+    quorum: Option<usize>,
+    race_stagger_ms: Option<u64>,
+    exec_timeout_seconds: Option<u64>,
+    #[serde(default, rename = "domain")]
+    domains: Vec<FileDomain>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileRetry {
+    attempts: Option<u32>,
+    base_ms: Option<u64>,
+    max_ms: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileDomain {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    password: String,
+    /// Read the password from this file instead (e.g. a mounted secret).
+    password_file: Option<PathBuf>,
+    #[serde(default)]
+    hosts: Vec<String>,
+    #[serde(default)]
+    aaaa_hosts: Vec<String>,
+    interval_seconds: Option<u64>,
+}
+
+/// Which config file to use: `--config`, then `NC_CONFIG`, then `default`
+/// if it exists. `None` means the environment is the config.
+fn config_path(
+    explicit: Option<PathBuf>,
+    from_env: Option<PathBuf>,
+    default: &Path,
+) -> Option<PathBuf> {
+    explicit
+        .or(from_env)
+        .or_else(|| default.exists().then(|| default.to_path_buf()))
+}
+
+/// 1-based line and column of byte `offset` in `text`.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+/// Whether byte `offset` of `text` is on a `password = ...` line.
+fn is_password_line(text: &str, offset: usize) -> bool {
+    let start = text[..offset.min(text.len())]
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    let line = text[start..].lines().next().unwrap_or("");
+    line.split_once('=')
+        .is_some_and(|(key, _)| key.trim().trim_matches(|c| c == '"' || c == '\'') == "password")
+}
+
+fn trim_hosts(hosts: Vec<String>) -> Vec<String> {
+    hosts
+        .into_iter()
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .collect()
+}
+
+fn split_list(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Strip the trailing newline editors and `echo` leave in secret files.
+fn trim_secret(raw: String) -> String {
+    raw.trim_end_matches(['\n', '\r']).to_string()
+}
+
+/// Read `name` from the environment, or from the file named by `{name}_FILE`
+/// (Docker/Kubernetes secrets). Setting both is a problem, as is an
+/// unreadable file.
+fn read_var(name: &str, problems: &mut Vec<String>) -> Option<String> {
+    let file_var = format!("{name}_FILE");
+
+    match (env::var_os(name), env::var_os(&file_var)) {
+        (Some(_), Some(_)) => {
+            problems.push(format!("both {name} and {file_var} are set, use only one"));
+            None
+        }
+        (None, Some(path)) => match fs::read_to_string(&path) {
+            Ok(raw) => Some(trim_secret(raw)),
+            Err(e) => {
+                problems.push(format!(
+                    "{file_var}: failed to read {}: {e}",
+                    Path::new(&path).display()
+                ));
+                None
+            }
+        },
+        (Some(_), None) => match env::var(name) {
+            Ok(v) => Some(v),
+            Err(_) => {
+                problems.push(format!("{name} is not valid UTF-8"));
+                None
+            }
+        },
+        (None, None) => None,
+    }
+}
+
+/// Parse an optional numeric env var, recording a problem if it isn't one.
+fn opt_number_var<T: std::str::FromStr>(name: &str, problems: &mut Vec<String>) -> Option<T> {
+    let raw = read_var(name, problems)?;
+    match raw.trim().parse() {
+        Ok(n) => Some(n),
+        Err(_) => {
+            problems.push(format!("{name} must be an integer, got {raw:?}"));
+            None
+        }
+    }
+}
+
+/// Parse an optional boolean env var (`1/true/yes/on`, `0/false/no/off`).
+fn flag_var(name: &str, problems: &mut Vec<String>) -> bool {
+    let Some(raw) = read_var(name, problems) else {
+        return false;
+    };
+    match raw.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => true,
+        "0" | "false" | "no" | "off" | "" => false,
+        _ => {
+            problems.push(format!("{name} must be true or false, got {raw:?}"));
+            false
+        }
+    }
+}
+
+/// Read a boolean flag such as `NC_ONCE` from the environment, honouring
+/// `{name}_FILE` like every other variable. Unset means `false`.
+pub fn env_flag(name: &str) -> Result<bool, ConfigError> {
+    let mut problems = Vec::new();
+    let value = flag_var(name, &mut problems);
+    if problems.is_empty() {
+        Ok(value)
+    } else {
+        Err(ConfigError { problems })
+    }
+}
+
+fn number_var<T: std::str::FromStr>(name: &str, default: T, problems: &mut Vec<String>) -> T {
+    opt_number_var(name, problems).unwrap_or(default)
+}
+
+/// Build the detection strategy from `detect_mode` and its options. Quorum
+/// defaults to a majority of the configured providers.
+fn detect_strategy(
+    mode: Option<&str>,
+    quorum: Option<usize>,
+    stagger_ms: Option<u64>,
+    providers: &[String],
+    problems: &mut Vec<String>,
+) -> DetectStrategy {
+    let mode = mode.map(|m| m.trim().to_lowercase());
+    let mode = mode.as_deref().unwrap_or("sequential");
+
+    if quorum.is_some() && mode != "quorum" {
+        problems.push("quorum is only used with detect mode \"quorum\"".to_string());
+    }
+    if stagger_ms.is_some() && mode != "race" {
+        problems.push("race stagger is only used with detect mode \"race\"".to_string());
+    }
+
+    match mode {
+        "sequential" => DetectStrategy::Sequential,
+        "quorum" => DetectStrategy::Quorum {
+            min_agree: quorum.unwrap_or(providers.len() / 2 + 1),
+        },
+        "race" => DetectStrategy::Race {
+            stagger: Duration::from_millis(stagger_ms.unwrap_or(DEFAULT_RACE_STAGGER_MS)),
+        },
+        other => {
+            problems.push(format!(
+                "unknown detect mode {other:?} (expected \"sequential\", \"quorum\" or \"race\")"
+            ));
+            DetectStrategy::Sequential
+        }
+    }
+}
+
+/// IPv6 detection runs in the same mode as IPv4. Without an explicit
+/// quorum, the majority is taken over the IPv6 providers instead.
+fn ipv6_options(
+    v4: DetectOptions,
+    quorum: Option<usize>,
+    ip6_providers: &[String],
+) -> DetectOptions {
+    let strategy = match v4.strategy {
+        DetectStrategy::Quorum { .. } if quorum.is_none() => DetectStrategy::Quorum {
+            min_agree: ip6_providers.len() / 2 + 1,
+        },
+        strategy => strategy,
+    };
+    DetectOptions {
+        strategy,
+        family: Family::V6,
+        ..v4
+    }
+}
+
+/// Provider health lives in the same directory as the state file.
+fn health_path(state_path: &Path) -> PathBuf {
+    state_path.with_file_name("provider_health.json")
+}
+
+/// Like `read_var`, but the value must be present and non-blank.
+fn required_var(name: &str, problems: &mut Vec<String>) -> String {
+    let before = problems.len();
+    match read_var(name, problems) {
+        Some(v) if !v.trim().is_empty() => v,
+        Some(_) => {
+            problems.push(format!("{name} is empty"));
+            String::new()
+        }
+        None => {
+            // Only complain about absence if reading didn't already fail.
+            if problems.len() == before {
+                problems.push(format!("{name} env var missing"));
+            }
+            String::new()
+        }
+    }
+}
+
+/// `example.com`, `sub.example.co.uk`, ...
+fn is_valid_domain(domain: &str) -> bool {
+    domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Namecheap host records: `@`, `*`, `www`, `home.lab`, `*.lab`, `_acme`, ...
+fn is_valid_host(host: &str) -> bool {
+    host == "@"
+        || host.split('.').all(|label| {
+            label == "*"
+                || (!label.is_empty()
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        })
+}
+
+pub(crate) fn is_http_url(s: &str) -> bool {
+    Url::parse(s)
+        .map(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+        .unwrap_or(false)
+}
+
+fn check_providers(
+    label: &str,
+    providers: &[String],
+    detect: &DetectOptions,
+    problems: &mut Vec<String>,
+) {
+    if providers.is_empty() {
+        problems.push(format!("no {label} providers configured"));
+    }
+    for p in providers {
+        if let Err(e) = Provider::parse(p) {
+            problems.push(format!("{label}: {e}"));
+        }
+    }
+
+    if let DetectStrategy::Quorum { min_agree } = detect.strategy {
+        if min_agree == 0 || min_agree > providers.len() {
+            problems.push(format!(
+                "quorum must be between 1 and the number of {label} providers ({}), got {}",
+                providers.len(),
+                min_agree
+            ));
+        }
+    }
+}
+
+impl Config {
+    /// `domains` with every other setting at its default, as if no other
+    /// `NC_*` variable were set. Call `validate` before using it.
+    pub fn new(domains: Vec<DomainConfig>) -> Self {
+        let ip_providers = split_list(DEFAULT_IP_PROVIDERS);
+        let ip6_providers = split_list(DEFAULT_IP6_PROVIDERS);
+        let detect = DetectOptions::default();
+        Config {
+            domains,
+            detect6: ipv6_options(detect, None, &ip6_providers),
+            ip_providers,
+            ip6_providers,
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            state_path: PathBuf::from(DEFAULT_STATE_PATH),
+            provider_health_path: None,
+            retry: Backoff::default(),
+            detect,
+            verify_dns: false,
+            dns_servers: split_list(DEFAULT_DNS_SERVERS),
+            propagation_timeout: None,
+        }
+    }
+
+    /// Whether any domain has AAAA hosts, i.e. IPv6 must be detected.
+    pub fn wants_ipv6(&self) -> bool {
+        self.domains.iter().any(|d| !d.aaaa_hosts.is_empty())
+    }
+
+    /// Load the config from `explicit` (from `--config`), then `NC_CONFIG`,
+    /// then `/data/config.toml` if it exists, and finally fall back to the
+    /// single-domain `NC_*` environment variables.
+    pub fn load(explicit: Option<PathBuf>) -> Result<Self, ConfigError> {
+        let mut problems = Vec::new();
+        let from_env = read_var("NC_CONFIG", &mut problems).map(PathBuf::from);
+        if !problems.is_empty() {
+            return Err(ConfigError { problems });
+        }
+
+        match config_path(explicit, from_env, Path::new(DEFAULT_CONFIG_PATH)) {
+            Some(path) => {
+                let single_domain_vars = ["NC_DOMAIN", "NC_PASSWORD", "NC_HOSTS"];
+                if single_domain_vars
+                    .iter()
+                    .any(|v| env::var_os(v).is_some() || env::var_os(format!("{v}_FILE")).is_some())
+                {
+                    warn!(
+                        "Using config file {}; ignoring NC_DOMAIN/NC_PASSWORD/NC_HOSTS",
+                        path.display()
+                    );
+                }
+                Config::from_file(&path)
+            }
+            None => Config::from_env(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let raw = fs::read_to_string(path).map_err(|e| {
+            ConfigError::single(format!(
+                "failed to read config file {}: {e}",
+                path.display()
+            ))
+        })?;
+        // Only the message and position: toml's Display quotes the offending
+        // line, and on a password line the message itself quotes the value.
+        let file: FileConfig = toml::from_str(&raw).map_err(|e| {
+            let span = e.span();
+            let at = span
+                .clone()
+                .map(|span| {
+                    let (line, column) = line_column(&raw, span.start);
+                    format!(" at line {line}, column {column}")
+                })
+                .unwrap_or_default();
+            let message = match span {
+                Some(span) if is_password_line(&raw, span.start) => "password must be a string",
+                _ => e.message(),
+            };
+            ConfigError::single(format!(
+                "invalid config file {}{at}: {message}",
+                path.display()
+            ))
+        })?;
+
+        let mut problems = Vec::new();
+        let default_interval = file.interval_seconds.unwrap_or(DEFAULT_INTERVAL_SECS);
+
+        if file.domains.is_empty() {
+            problems.push("config file defines no [[domain]] entries".to_string());
+        }
+
+        let domains = file
+            .domains
+            .into_iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let hosts = trim_hosts(d.hosts);
+                let aaaa_hosts = trim_hosts(d.aaaa_hosts);
+
+                if d.name.trim().is_empty() {
+                    problems.push(format!("domain #{}: name is missing", i + 1));
+                }
+                let password = match d.password_file {
+                    Some(_) if !d.password.is_empty() => {
+                        problems.push(format!(
+                            "domain #{}: both password and password_file are set, use only one",
+                            i + 1
+                        ));
+                        String::new()
+                    }
+                    Some(file) => match fs::read_to_string(&file) {
+                        Ok(raw) => trim_secret(raw),
+                        Err(e) => {
+                            problems.push(format!(
+                                "domain #{}: failed to read password_file {}: {e}",
+                                i + 1,
+                                file.display()
+                            ));
+                            String::new()
+                        }
+                    },
+                    None if d.password.is_empty() => {
+                        problems.push(format!("domain #{}: password is missing", i + 1));
+                        String::new()
+                    }
+                    None => d.password,
+                };
+                if hosts.is_empty() && aaaa_hosts.is_empty() {
+                    problems.push(format!("domain #{}: host list is empty", i + 1));
+                }
+
+                DomainConfig {
+                    domain: d.name.trim().to_string(),
+                    password,
+                    hosts,
+                    aaaa_hosts,
+                    interval_secs: d.interval_seconds.unwrap_or(default_interval),
+                }
+            })
+            .collect();
+
+        let ip_providers = file
+            .ip_providers
+            .unwrap_or_else(|| split_list(DEFAULT_IP_PROVIDERS));
+        let detect = DetectOptions {
+            strategy: detect_strategy(
+                file.detect_mode.as_deref(),
+                file.quorum,
+                file.race_stagger_ms,
+                &ip_providers,
+                &mut problems,
+            ),
+            allow_private: file.allow_private_ip,
+            family: Family::V4,
+            exec_timeout: file
+                .exec_timeout_seconds
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_EXEC_TIMEOUT),
+        };
+        let ip6_providers = file
+            .ip6_providers
+            .unwrap_or_else(|| split_list(DEFAULT_IP6_PROVIDERS));
+        let detect6 = ipv6_options(detect, file.quorum, &ip6_providers);
+
+        let state_path = file
+            .state_file
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_PATH));
+
+        Config {
+            domains,
+            ip_providers,
+            ip6_providers,
+            endpoint: file
+                .endpoint
+                .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string()),
+            provider_health_path: file
+                .persist_provider_health
+                .then(|| health_path(&state_path)),
+            state_path,
+            retry: match file.retry {
+                Some(r) => {
+                    let default = Backoff::default();
+                    Backoff::new(
+                        r.attempts.unwrap_or(default.max_attempts),
+                        r.base_ms
+                            .map(Duration::from_millis)
+                            .unwrap_or(default.base_delay),
+                        r.max_ms
+                            .map(Duration::from_millis)
+                            .unwrap_or(default.max_delay),
+                    )
+                }
+                None => Backoff::default(),
+            },
+            detect,
+            detect6,
+            verify_dns: file.verify_dns,
+            dns_servers: file
+                .dns_servers
+                .unwrap_or_else(|| split_list(DEFAULT_DNS_SERVERS)),
+            propagation_timeout: file
+                .propagation_timeout_seconds
+                .filter(|&s| s > 0)
+                .map(Duration::from_secs),
+        }
+        .checked(problems)
+    }
+
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mut problems = Vec::new();
+
+        let domain = required_var("NC_DOMAIN", &mut problems);
+        let password = required_var("NC_PASSWORD", &mut problems);
+        let hosts_raw = required_var("NC_HOSTS", &mut problems);
+
+        let interval_secs = number_var("NC_INTERVAL_SECONDS", DEFAULT_INTERVAL_SECS, &mut problems);
+
+        let default_retry = Backoff::default();
+        let retry = Backoff::new(
+            number_var(
+                "NC_RETRY_ATTEMPTS",
+                default_retry.max_attempts,
+                &mut problems,
+            ),
+            Duration::from_millis(number_var(
+                "NC_RETRY_BASE_MS",
+                default_retry.base_delay.as_millis() as u64,
+                &mut problems,
+            )),
+            Duration::from_millis(number_var(
+                "NC_RETRY_MAX_MS",
+                default_retry.max_delay.as_millis() as u64,
+                &mut problems,
+            )),
+        );
+
+        let ip_providers = split_list(
+            &read_var("NC_IP_PROVIDERS", &mut problems)
+                .unwrap_or_else(|| DEFAULT_IP_PROVIDERS.to_string()),
+        );
+
+        let quorum = opt_number_var("NC_QUORUM", &mut problems);
+        let stagger_ms = opt_number_var("NC_RACE_STAGGER_MS", &mut problems);
+        let detect = DetectOptions {
+            strategy: detect_strategy(
+                read_var("NC_DETECT_MODE", &mut problems).as_deref(),
+                quorum,
+                stagger_ms,
+                &ip_providers,
+                &mut problems,
+            ),
+            allow_private: flag_var("NC_ALLOW_PRIVATE_IP", &mut problems),
+            family: Family::V4,
+            exec_timeout: opt_number_var("NC_EXEC_TIMEOUT_SECONDS", &mut problems)
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_EXEC_TIMEOUT),
+        };
+        let ip6_providers = split_list(
+            &read_var("NC_IP6_PROVIDERS", &mut problems)
+                .unwrap_or_else(|| DEFAULT_IP6_PROVIDERS.to_string()),
+        );
+        let detect6 = ipv6_options(detect, quorum, &ip6_providers);
+        let aaaa_hosts = split_list(&read_var("NC_AAAA_HOSTS", &mut problems).unwrap_or_default());
+
+        let endpoint = read_var("NC_ENDPOINT", &mut problems)
+            .map(|e| e.trim().to_string())
+            .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+
+        let state_path = read_var("NC_STATE_FILE", &mut problems)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_PATH));
+        let provider_health_path =
+            flag_var("NC_PERSIST_PROVIDER_HEALTH", &mut problems).then(|| health_path(&state_path));
+
+        let hosts = split_list(&hosts_raw);
+        if !hosts_raw.trim().is_empty() && hosts.is_empty() {
+            problems.push("NC_HOSTS contains no hosts".to_string());
+        }
+
+        Config {
+            domains: vec![DomainConfig {
+                domain: domain.trim().to_string(),
+                password,
+                hosts,
+                aaaa_hosts,
+                interval_secs,
+            }],
+            ip_providers,
+            ip6_providers,
+            endpoint,
+            state_path,
+            provider_health_path,
+            retry,
+            detect,
+            detect6,
+            verify_dns: flag_var("NC_VERIFY_DNS", &mut problems),
+            dns_servers: split_list(
+                &read_var("NC_DNS_SERVERS", &mut problems)
+                    .unwrap_or_else(|| DEFAULT_DNS_SERVERS.to_string()),
+            ),
+            propagation_timeout: opt_number_var("NC_PROPAGATION_TIMEOUT_SECONDS", &mut problems)
+                .filter(|&s| s > 0)
+                .map(Duration::from_secs),
+        }
+        .checked(problems)
+    }
+
+    /// Run `validate` and merge its findings with the `problems` the loader
+    /// already collected (missing values and the like).
+    fn checked(self, mut problems: Vec<String>) -> Result<Self, ConfigError> {
+        if let Err(e) = self.validate() {
+            problems.extend(e.problems);
+        }
+        if problems.is_empty() {
+            Ok(self)
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    /// Check the values themselves, independent of where they came from.
+    /// Missing values are the loader's job and are not reported again here.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+
+        for d in &self.domains {
+            if d.domain.is_empty() {
+                // already reported as missing
+            } else if !is_valid_domain(&d.domain) {
+                problems.push(format!("domain {:?} is not a valid domain name", d.domain));
+            } else if !seen.insert(d.domain.to_ascii_lowercase()) {
+                problems.push(format!(
+                    "domain {:?} is configured more than once",
+                    d.domain
+                ));
+            }
+            for host in d.hosts.iter().chain(&d.aaaa_hosts) {
+                if !is_valid_host(host) {
+                    problems.push(format!(
+                        "domain {:?}: host {:?} contains illegal characters",
+                        d.domain, host
+                    ));
+                }
+            }
+            if d.interval_secs == 0 {
+                problems.push(format!(
+                    "domain {:?}: interval must be greater than 0",
+                    d.domain
+                ));
+            }
+        }
+
+        check_providers("IP", &self.ip_providers, &self.detect, &mut problems);
+        if self.wants_ipv6() {
+            check_providers("IPv6", &self.ip6_providers, &self.detect6, &mut problems);
+        }
+
+        if self.retry.max_attempts == 0 {
+            problems.push("retry attempts must be at least 1".to_string());
+        }
+        if self.retry.base_delay > self.retry.max_delay {
+            problems.push("retry base delay must not exceed the max delay".to_string());
+        }
+        if (self.verify_dns || self.propagation_timeout.is_some()) && self.dns_servers.is_empty() {
+            problems
+                .push("DNS verification is enabled but no DNS servers are configured".to_string());
+        }
+        if self.detect.exec_timeout.is_zero() {
+            problems.push("exec timeout must be greater than 0".to_string());
+        }
+
+        if !is_http_url(&self.endpoint) {
+            problems.push(format!(
+                "endpoint {:?} is not a valid http(s) URL",
+                self.endpoint
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::config_path;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn config_path_precedence() {
+        let existing = std::env::temp_dir();
+        let missing = Path::new("/nonexistent/namecheap-ddns/config.toml");
+        let flag = Some(PathBuf::from("flag.toml"));
+        let var = Some(PathBuf::from("var.toml"));
+
+        assert_eq!(config_path(flag.clone(), var.clone(), &existing), flag);
+        assert_eq!(config_path(None, var.clone(), &existing), var);
+        assert_eq!(config_path(None, None, &existing), Some(existing.clone()));
+        assert_eq!(config_path(None, None, missing), None);
+    }
+}
@@ -0,0 +1,495 @@
+use log::{debug, info, warn};
+use reqwest::Client;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::{timeout, Instant};
+
+use crate::dns;
+use crate::error::DetectError;
+use crate::exec::run_command;
+use crate::health::{Outcome, ProviderHealth};
+use crate::interface::interface_address;
+use crate::provider::{Extract, Provider};
+use crate::router::{query_natpmp, query_upnp};
+use crate::stun::query_stun;
+
+/// How the configured IP providers are combined into one answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectStrategy {
+    /// Try providers in order and trust the first valid answer.
+    Sequential,
+    /// Query every provider concurrently and only accept an IP reported by
+    /// at least `min_agree` of them.
+    Quorum { min_agree: usize },
+    /// Start providers `stagger` apart (all at once if zero), starting the
+    /// next one early whenever one fails, and return the first valid answer.
+    /// The remaining requests are cancelled.
+    Race { stagger: Duration },
+}
+
+/// Address family: IPv4 (A records) or IPv6 (AAAA records).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    pub fn record_type(self) -> &'static str {
+        match self {
+            Family::V4 => "A",
+            Family::V6 => "AAAA",
+        }
+    }
+
+    pub fn matches(self, ip: &IpAddr) -> bool {
+        match self {
+            Family::V4 => ip.is_ipv4(),
+            Family::V6 => ip.is_ipv6(),
+        }
+    }
+
+    /// Wildcard local address; binding to it forces connections onto this
+    /// family even for dual-stack provider hostnames.
+    pub fn unspecified(self) -> IpAddr {
+        match self {
+            Family::V4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            Family::V6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Family::V4 => "IPv4",
+            Family::V6 => "IPv6",
+        })
+    }
+}
+
+pub(crate) const DEFAULT_EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Detection settings beyond the provider list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DetectOptions {
+    pub strategy: DetectStrategy,
+    /// Accept private, CGNAT, loopback and other non-public addresses.
+    pub allow_private: bool,
+    /// Which address family the providers are expected to return.
+    pub family: Family,
+    /// How long an `exec:` provider may run before it is killed.
+    pub exec_timeout: Duration,
+}
+
+impl Default for DetectOptions {
+    fn default() -> Self {
+        DetectOptions {
+            strategy: DetectStrategy::Sequential,
+            allow_private: false,
+            family: Family::V4,
+            exec_timeout: DEFAULT_EXEC_TIMEOUT,
+        }
+    }
+}
+
+impl DetectOptions {
+    pub fn with_strategy(mut self, strategy: DetectStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_allow_private(mut self, allow_private: bool) -> Self {
+        self.allow_private = allow_private;
+        self
+    }
+
+    pub fn with_family(mut self, family: Family) -> Self {
+        self.family = family;
+        self
+    }
+
+    pub fn with_exec_timeout(mut self, exec_timeout: Duration) -> Self {
+        self.exec_timeout = exec_timeout;
+        self
+    }
+}
+
+/// Whether `ip` is routable on the public internet. Rejects RFC 1918,
+/// CGNAT (100.64/10), loopback, link-local, multicast, broadcast,
+/// documentation, benchmarking and other reserved ranges; a captive portal
+/// or misconfigured proxy answering with one of these must not end up in
+/// public DNS.
+pub fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_multicast()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || a == 0
+        // CGNAT shared address space, 100.64.0.0/10
+        || (a == 100 && (b & 0b1100_0000) == 64)
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && (b & 0xfe) == 18)
+        // Reserved for future use, 240.0.0.0/4
+        || a >= 240)
+}
+
+/// Whether `ip` is a global unicast IPv6 address (2000::/3), excluding the
+/// documentation (2001:db8::/32) and benchmarking (2001:2::/48) ranges.
+/// ULA, link-local, loopback and multicast addresses all fall outside
+/// 2000::/3.
+pub fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let s = ip.segments();
+    (s[0] & 0xe000) == 0x2000
+        && !(s[0] == 0x2001 && s[1] == 0x0db8)
+        && !(s[0] == 0x2001 && s[1] == 0x0002 && s[2] == 0)
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+/// Parse a provider answer, rejecting other families and (unless allowed)
+/// non-public addresses.
+fn validate_answer(p: &str, text: &str, options: DetectOptions) -> Option<String> {
+    let ip = match text.trim().parse::<IpAddr>() {
+        Ok(ip) if options.family.matches(&ip) => ip,
+        _ => {
+            let preview: String = text.chars().take(80).collect();
+            warn!(
+                "Provider {} returned non-{}: {:?}",
+                p, options.family, preview
+            );
+            return None;
+        }
+    };
+
+    if !options.allow_private && !is_public_ip(ip) {
+        warn!(
+            "Provider {} returned non-public address {} \
+             (set NC_ALLOW_PRIVATE_IP=1 if this is intentional)",
+            p, ip
+        );
+        return None;
+    }
+
+    Some(ip.to_string())
+}
+
+/// Ask a single provider for our public address. Failures are logged and
+/// reported as `None`.
+async fn query_provider(client: &Client, p: &str, options: DetectOptions) -> Option<String> {
+    info!("Trying IP provider: {}", p);
+
+    match Provider::parse(p) {
+        Ok(Provider::Http { url, extract }) => query_http(client, p, url, &extract, options).await,
+        Ok(Provider::Interface(name)) => interface_address(name, options),
+        Ok(Provider::Dns { name, server, txt }) => validated(
+            p,
+            dns::query_own_address(name, server, txt, options.family).await,
+            options,
+        ),
+        Ok(Provider::Stun(server)) => {
+            validated(p, query_stun(server, options.family).await, options)
+        }
+        Ok(Provider::Upnp(description)) => validated(
+            p,
+            query_upnp(client, description, options.family).await,
+            options,
+        ),
+        Ok(Provider::NatPmp(gateway)) => {
+            validated(p, query_natpmp(gateway, options.family).await, options)
+        }
+        Ok(Provider::Exec(command)) => {
+            validated(p, run_command(command, options.exec_timeout).await, options)
+        }
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    }
+}
+
+/// Validate the answer of a non-HTTP provider, logging its failure.
+fn validated(p: &str, answer: io::Result<String>, options: DetectOptions) -> Option<String> {
+    match answer {
+        Ok(text) => validate_answer(p, &text, options),
+        Err(e) => {
+            warn!("Provider {} failed: {}", p, e);
+            None
+        }
+    }
+}
+
+async fn query_http(
+    client: &Client,
+    p: &str,
+    url: &str,
+    extract: &Extract,
+    options: DetectOptions,
+) -> Option<String> {
+    match client.get(url).send().await {
+        Ok(resp) if resp.status().is_success() => {
+            let text = match resp.text().await {
+                Ok(text) => text,
+                Err(e) => {
+                    warn!("Provider {} failed: {}", p, e);
+                    return None;
+                }
+            };
+            match extract.apply(&text) {
+                Ok(answer) => validate_answer(p, &answer, options),
+                Err(e) => {
+                    warn!("Provider {} returned no address: {}", p, e);
+                    None
+                }
+            }
+        }
+        Ok(resp) => {
+            warn!("Provider {} returned status {}", p, resp.status());
+            None
+        }
+        Err(e) => {
+            warn!("Provider {} failed: {}", p, e);
+            None
+        }
+    }
+}
+
+/// Ask each provider in turn for our public IPv4 and return the first valid
+/// answer. See `detect_ip_with` for other strategies and IPv6.
+pub async fn detect_ip(client: &Client, providers: &[String]) -> Result<String, DetectError> {
+    detect_ip_with(client, providers, &DetectOptions::default()).await
+}
+
+/// Detect our public address as configured by `options`. For IPv6, pass a
+/// client from `http_client_for(Family::V6)` so dual-stack providers are
+/// reached over the right family.
+pub async fn detect_ip_with(
+    client: &Client,
+    providers: &[String],
+    options: &DetectOptions,
+) -> Result<String, DetectError> {
+    detect(client, providers, *options, &mut Vec::new()).await
+}
+
+/// Like `detect_ip_with`, but tries providers in order of their `health`
+/// and updates it with how each provider did.
+pub async fn detect_ip_scored(
+    client: &Client,
+    providers: &[String],
+    options: &DetectOptions,
+    health: &mut ProviderHealth,
+) -> Result<String, DetectError> {
+    let (ordered, changed) = health.reorder(options.family, providers);
+    if changed {
+        info!(
+            "{} provider order is now {}; health: {}",
+            options.family,
+            ordered.join(", "),
+            health.summary(options.family, providers)
+        );
+    }
+
+    let mut outcomes = Vec::new();
+    let result = detect(client, &ordered, *options, &mut outcomes).await;
+    for outcome in &outcomes {
+        health.record(options.family, outcome);
+    }
+    debug!(
+        "{} provider health: {}",
+        options.family,
+        health.summary(options.family, providers)
+    );
+    result
+}
+
+async fn detect(
+    client: &Client,
+    providers: &[String],
+    options: DetectOptions,
+    outcomes: &mut Vec<Outcome>,
+) -> Result<String, DetectError> {
+    match options.strategy {
+        DetectStrategy::Sequential => detect_sequential(client, providers, options, outcomes).await,
+        DetectStrategy::Quorum { min_agree } => {
+            detect_quorum(client, providers, min_agree, options, outcomes).await
+        }
+        DetectStrategy::Race { stagger } => {
+            detect_race(client, providers, stagger, options, outcomes).await
+        }
+    }
+}
+
+/// `query_provider`, also reporting how long it took.
+async fn timed_query(
+    client: &Client,
+    p: &str,
+    options: DetectOptions,
+) -> (Option<String>, Duration) {
+    let started = Instant::now();
+    let ip = query_provider(client, p, options).await;
+    (ip, started.elapsed())
+}
+
+async fn detect_sequential(
+    client: &Client,
+    providers: &[String],
+    options: DetectOptions,
+    outcomes: &mut Vec<Outcome>,
+) -> Result<String, DetectError> {
+    for p in providers {
+        if p.is_empty() {
+            continue;
+        }
+        let (ip, latency) = timed_query(client, p, options).await;
+        outcomes.push(Outcome {
+            provider: p.clone(),
+            ok: ip.is_some(),
+            latency,
+        });
+        if let Some(ip) = ip {
+            return Ok(ip);
+        }
+    }
+    Err(DetectError::NoValidAddress)
+}
+
+async fn detect_race(
+    client: &Client,
+    providers: &[String],
+    stagger: Duration,
+    options: DetectOptions,
+    outcomes: &mut Vec<Outcome>,
+) -> Result<String, DetectError> {
+    let mut pending = providers.iter().filter(|p| !p.is_empty()).peekable();
+    let mut tasks = JoinSet::new();
+
+    loop {
+        if let Some(p) = pending.next() {
+            let client = client.clone();
+            let p = p.clone();
+            tasks.spawn(async move {
+                let (ip, latency) = timed_query(&client, &p, options).await;
+                (p, ip, latency)
+            });
+        } else if tasks.is_empty() {
+            return Err(DetectError::NoValidAddress);
+        }
+
+        // Wait for an answer; give up waiting after `stagger` if another
+        // provider could be started.
+        let joined = if pending.peek().is_some() {
+            match timeout(stagger, tasks.join_next()).await {
+                Ok(joined) => joined,
+                Err(_) => continue,
+            }
+        } else {
+            tasks.join_next().await
+        };
+
+        if let Some(Ok((p, ip, latency))) = joined {
+            outcomes.push(Outcome {
+                provider: p,
+                ok: ip.is_some(),
+                latency,
+            });
+            if let Some(ip) = ip {
+                // Dropping the set would do this too; be explicit. Providers
+                // cut short this way are not counted against their health.
+                tasks.abort_all();
+                return Ok(ip);
+            }
+        }
+    }
+}
+
+async fn detect_quorum(
+    client: &Client,
+    providers: &[String],
+    min_agree: usize,
+    options: DetectOptions,
+    outcomes: &mut Vec<Outcome>,
+) -> Result<String, DetectError> {
+    let mut tasks = JoinSet::new();
+    for p in providers.iter().filter(|p| !p.is_empty()) {
+        let client = client.clone();
+        let p = p.clone();
+        tasks.spawn(async move {
+            let (ip, latency) = timed_query(&client, &p, options).await;
+            (p, ip, latency)
+        });
+    }
+
+    let mut answers: Vec<(String, String)> = Vec::new();
+    let mut timings: Vec<(String, Duration)> = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((p, ip, latency)) = joined {
+            timings.push((p.clone(), latency));
+            if let Some(ip) = ip {
+                answers.push((p, ip));
+            }
+        }
+    }
+
+    let mut votes: HashMap<&str, usize> = HashMap::new();
+    for (_, ip) in &answers {
+        *votes.entry(ip.as_str()).or_default() += 1;
+    }
+
+    // Highest vote count wins; ties are broken by address for determinism.
+    let winner = votes
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)));
+
+    // Dissenters count as failures, just like providers that didn't answer.
+    for (p, latency) in timings {
+        let ok = winner.is_some_and(|(w, _)| answers.iter().any(|(ap, ip)| *ap == p && ip == w));
+        outcomes.push(Outcome {
+            provider: p,
+            ok,
+            latency,
+        });
+    }
+
+    let Some((winner, count)) = winner else {
+        return Err(DetectError::NoValidAddress);
+    };
+
+    for (p, ip) in answers.iter().filter(|(_, ip)| ip != winner) {
+        warn!(
+            "Provider {} reported {}, disagreeing with the majority answer {}",
+            p, ip, winner
+        );
+    }
+
+    if count < min_agree {
+        return Err(DetectError::NoQuorum {
+            best: winner.to_string(),
+            votes: count,
+            required: min_agree,
+        });
+    }
+
+    info!(
+        "IP {} confirmed by {} of {} answering providers",
+        winner,
+        count,
+        answers.len()
+    );
+    Ok(winner.to_string())
+}
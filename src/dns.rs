@@ -0,0 +1,405 @@
+//! Just enough of the DNS wire format (RFC 1035) to send a single question
+//! over UDP and read A, AAAA and TXT answers.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{lookup_host, UdpSocket};
+use tokio::time::{timeout_at, Instant};
+
+use crate::backoff::random_up_to;
+use crate::detect::Family;
+
+pub(crate) const DNS_PORT: u16 = 53;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Initial retransmission interval for queries, doubled after each try.
+const QUERY_RTO: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecordType {
+    A,
+    Aaaa,
+    Txt,
+}
+
+impl RecordType {
+    fn code(self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::Aaaa => 28,
+            RecordType::Txt => 16,
+        }
+    }
+
+    /// A or AAAA, matching `family`.
+    pub(crate) fn address(family: Family) -> Self {
+        match family {
+            Family::V4 => RecordType::A,
+            Family::V6 => RecordType::Aaaa,
+        }
+    }
+}
+
+/// One answer record we understand; others (CNAME, ...) are skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Answer {
+    Ip(IpAddr),
+    /// The character-strings of a TXT record, concatenated.
+    Txt(String),
+}
+
+/// An `InvalidData` error for a malformed reply, shared by the UDP
+/// protocols built on this module.
+pub(crate) fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Split `host`, `host:port` or `[v6]:port` into host and port.
+fn split_server(spec: &str, default_port: u16) -> (&str, u16) {
+    if let Some(rest) = spec.strip_prefix('[') {
+        if let Some((host, port)) = rest.split_once(']') {
+            let port = port.strip_prefix(':').and_then(|p| p.parse().ok());
+            return (host, port.unwrap_or(default_port));
+        }
+    }
+    match spec.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => match port.parse() {
+            Ok(port) => (host, port),
+            Err(_) => (spec, default_port),
+        },
+        _ => (spec, default_port),
+    }
+}
+
+/// Resolve a server given as `host[:port]` to an address of `family`, so
+/// the query (and thus the address the server sees) uses that family.
+pub(crate) async fn resolve_server(
+    spec: &str,
+    default_port: u16,
+    family: Family,
+) -> io::Result<SocketAddr> {
+    let (host, port) = split_server(spec, default_port);
+    lookup_host((host, port))
+        .await?
+        .find(|addr| family.matches(&addr.ip()))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("server {host} has no {family} address"),
+            )
+        })
+}
+
+fn encode_query(id: u16, name: &str, rtype: RecordType) -> io::Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(32 + name.len());
+    msg.extend_from_slice(&id.to_be_bytes());
+    // Standard query, recursion desired; one question.
+    msg.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid DNS name {name:?}"),
+            ));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    msg.extend_from_slice(&rtype.code().to_be_bytes());
+    msg.extend_from_slice(&1u16.to_be_bytes()); // class IN
+    Ok(msg)
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> io::Result<u16> {
+    msg.get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("truncated DNS message"))
+}
+
+/// Position just past the (possibly compressed) name starting at `pos`.
+fn skip_name(msg: &[u8], mut pos: usize) -> io::Result<usize> {
+    loop {
+        let len = *msg.get(pos).ok_or_else(|| invalid("truncated DNS name"))?;
+        match len {
+            0 => return Ok(pos + 1),
+            // Compression pointer: two bytes and the name ends here.
+            l if l & 0xc0 == 0xc0 => return Ok(pos + 2),
+            l => pos += 1 + l as usize,
+        }
+    }
+}
+
+fn parse_response(msg: &[u8]) -> io::Result<Vec<Answer>> {
+    let flags = read_u16(msg, 2)?;
+    if flags & 0x8000 == 0 {
+        return Err(invalid("DNS message is not a response"));
+    }
+    if flags & 0x0200 != 0 {
+        return Err(invalid("DNS response was truncated"));
+    }
+    match flags & 0x000f {
+        0 => {}
+        // NXDOMAIN: the name has no records at all.
+        3 => return Ok(Vec::new()),
+        rcode => return Err(invalid(format!("DNS server returned rcode {rcode}"))),
+    }
+
+    let questions = read_u16(msg, 4)?;
+    let answers = read_u16(msg, 6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(msg, pos)? + 4;
+    }
+
+    let mut out = Vec::new();
+    for _ in 0..answers {
+        pos = skip_name(msg, pos)?;
+        let rtype = read_u16(msg, pos)?;
+        let len = read_u16(msg, pos + 8)? as usize;
+        pos += 10;
+        let data = msg
+            .get(pos..pos + len)
+            .ok_or_else(|| invalid("truncated DNS record"))?;
+        pos += len;
+
+        match (rtype, data.len()) {
+            (1, 4) => out.push(Answer::Ip(IpAddr::V4(Ipv4Addr::new(
+                data[0], data[1], data[2], data[3],
+            )))),
+            (28, 16) => {
+                let bytes: [u8; 16] = data.try_into().expect("length checked");
+                out.push(Answer::Ip(IpAddr::V6(Ipv6Addr::from(bytes))));
+            }
+            (16, _) => {
+                let mut text = String::new();
+                let mut i = 0;
+                while let Some(&n) = data.get(i) {
+                    let chunk = data
+                        .get(i + 1..i + 1 + n as usize)
+                        .ok_or_else(|| invalid("truncated TXT record"))?;
+                    text.push_str(&String::from_utf8_lossy(chunk));
+                    i += 1 + n as usize;
+                }
+                out.push(Answer::Txt(text));
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// UDP socket of the same family as `server`, connected to it.
+pub(crate) async fn connect_udp(server: SocketAddr) -> io::Result<UdpSocket> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    Ok(socket)
+}
+
+/// Send `request` and wait for the datagram `reply` returns `Some` for,
+/// resending it after `rto` and doubling the wait each time until `limit`
+/// has passed. Datagrams `reply` returns `None` for belong to another
+/// exchange and are ignored.
+pub(crate) async fn exchange<T>(
+    socket: &UdpSocket,
+    request: &[u8],
+    rto: Duration,
+    limit: Duration,
+    what: &str,
+    mut reply: impl FnMut(&[u8]) -> Option<io::Result<T>>,
+) -> io::Result<T> {
+    let deadline = Instant::now() + limit;
+    let mut wait = rto;
+    let mut buf = [0u8; 1232];
+    loop {
+        socket.send(request).await?;
+        let resend_at = (Instant::now() + wait).min(deadline);
+        while let Ok(n) = timeout_at(resend_at, socket.recv(&mut buf)).await {
+            if let Some(result) = reply(&buf[..n?]) {
+                return result;
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{what} timed out"),
+            ));
+        }
+        wait *= 2;
+    }
+}
+
+/// Send one `rtype` question for `name` to `server` over UDP and return the
+/// answers we understand.
+pub(crate) async fn query(
+    server: SocketAddr,
+    name: &str,
+    rtype: RecordType,
+) -> io::Result<Vec<Answer>> {
+    let id = random_up_to(u16::MAX as u64) as u16;
+    let request = encode_query(id, name, rtype)?;
+
+    let socket = connect_udp(server).await?;
+    exchange(
+        &socket,
+        &request,
+        QUERY_RTO,
+        QUERY_TIMEOUT,
+        "DNS query",
+        // Ignore stray datagrams that don't answer our question.
+        |reply| (read_u16(reply, 0).ok() == Some(id)).then(|| parse_response(reply)),
+    )
+    .await
+}
+
+/// Ask `server` (`host[:port]`) for `name`, a record that resolves to the
+/// address the query came from (e.g. `myip.opendns.com`). With `txt`, the
+/// address is read from a TXT record instead (e.g. Google's
+/// `o-o.myaddr.l.google.com`).
+pub(crate) async fn query_own_address(
+    name: &str,
+    server: &str,
+    txt: bool,
+    family: Family,
+) -> io::Result<String> {
+    let server = resolve_server(server, DNS_PORT, family).await?;
+    let rtype = if txt {
+        RecordType::Txt
+    } else {
+        RecordType::address(family)
+    };
+    let answers = query(server, name, rtype).await?;
+
+    let text = answers.iter().find_map(|a| match a {
+        Answer::Ip(ip) if family.matches(ip) => Some(ip.to_string()),
+        _ => None,
+    });
+    // Prefer a TXT string that is an address; some servers add extra
+    // records (e.g. EDNS client subnet details).
+    let text = text.or_else(|| {
+        let txts = answers.iter().filter_map(|a| match a {
+            Answer::Txt(t) => Some(t.trim()),
+            _ => None,
+        });
+        let mut first = None;
+        for t in txts {
+            if t.parse::<IpAddr>().is_ok() {
+                return Some(t.to_string());
+            }
+            first.get_or_insert(t);
+        }
+        first.map(str::to_string)
+    });
+    text.ok_or_else(|| invalid(format!("no {rtype:?} answer for {name}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A response to a query for `name`, with `answers` as (type, rdata)
+    /// records whose owner name is a pointer back to the question.
+    fn response(rcode: u8, name: &str, answers: &[(RecordType, &[u8])]) -> Vec<u8> {
+        let mut msg = encode_query(0x1234, name, RecordType::A).unwrap();
+        msg[2] = 0x81;
+        msg[3] = 0x80 | rcode;
+        msg[7] = answers.len() as u8;
+        for (rtype, data) in answers {
+            msg.extend_from_slice(&[0xc0, 12]);
+            msg.extend_from_slice(&rtype.code().to_be_bytes());
+            msg.extend_from_slice(&[0, 1, 0, 0, 0x0e, 0x10]);
+            msg.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            msg.extend_from_slice(data);
+        }
+        msg
+    }
+
+    #[test]
+    fn reads_addresses_behind_compressed_names() {
+        let v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        let msg = response(
+            0,
+            "home.example.com",
+            &[
+                (RecordType::A, &[93, 184, 216, 34]),
+                (RecordType::Aaaa, &v6.octets()),
+            ],
+        );
+
+        assert_eq!(
+            parse_response(&msg).unwrap(),
+            vec![
+                Answer::Ip(IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34))),
+                Answer::Ip(IpAddr::V6(v6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_compression_pointers_inside_names() {
+        // "www" followed by a pointer to the question's "example.com".
+        let mut msg = response(0, "example.com", &[]);
+        msg[7] = 1;
+        msg.extend_from_slice(&[3, b'w', b'w', b'w', 0xc0, 12]);
+        msg.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 198, 51, 100, 7]);
+
+        assert_eq!(
+            parse_response(&msg).unwrap(),
+            vec![Answer::Ip(IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7)))]
+        );
+    }
+
+    #[test]
+    fn joins_txt_chunks() {
+        let msg = response(
+            0,
+            "o-o.myaddr.l.google.com",
+            &[(RecordType::Txt, b"\x0593.18\x054.216\x00\x03.34")],
+        );
+
+        assert_eq!(
+            parse_response(&msg).unwrap(),
+            vec![Answer::Txt("93.184.216.34".to_string())]
+        );
+    }
+
+    #[test]
+    fn nxdomain_has_no_answers_but_other_errors_fail() {
+        assert_eq!(
+            parse_response(&response(3, "nope.example", &[])).unwrap(),
+            vec![]
+        );
+
+        let err = parse_response(&response(2, "example.com", &[])).unwrap_err();
+        assert!(err.to_string().contains("rcode 2"), "{err}");
+    }
+
+    #[test]
+    fn truncated_messages_are_errors() {
+        let msg = response(0, "example.com", &[(RecordType::A, &[93, 184, 216, 34])]);
+        for len in [0, 3, 12, 20, msg.len() - 12, msg.len() - 1] {
+            let err = parse_response(&msg[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{len}: {err}");
+        }
+
+        let txt = response(0, "example.com", &[(RecordType::Txt, b"\x0atoo short")]);
+        let err = parse_response(&txt).unwrap_err();
+        assert!(err.to_string().contains("truncated TXT record"), "{err}");
+
+        // The TC bit means the answer didn't fit in a UDP datagram.
+        let mut tc = msg.clone();
+        tc[2] |= 0x02;
+        let err = parse_response(&tc).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{err}");
+    }
+
+    #[test]
+    fn queries_are_not_mistaken_for_responses() {
+        let query = encode_query(1, "example.com", RecordType::A).unwrap();
+        assert!(parse_response(&query).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use reqwest::StatusCode;
+use std::fmt;
+
+use crate::response::NamecheapResponse;
+
+/// Why a single Namecheap DDNS update did not go through.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum UpdateError {
+    /// The request failed before we got a response (DNS, connect, timeout, TLS, ...).
+    Transport(reqwest::Error),
+    /// Namecheap answered with a non-2xx HTTP status.
+    HttpStatus(StatusCode),
+    /// Namecheap answered with `ErrCount > 0`.
+    Namecheap(Box<NamecheapResponse>),
+    /// The response body was not valid Namecheap XML.
+    Malformed(String),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateError::Transport(e) => write!(f, "request failed: {e}"),
+            UpdateError::HttpStatus(status) => write!(
+                f,
+                "unexpected HTTP status {} {}",
+                status.as_u16(),
+                status.canonical_reason().unwrap_or("")
+            ),
+            UpdateError::Namecheap(response) => {
+                write!(f, "Namecheap reported ErrCount={}", response.err_count)?;
+                let codes = response.codes();
+                let messages = response.messages();
+                if !codes.is_empty() {
+                    let codes: Vec<String> = codes.iter().map(|c| c.to_string()).collect();
+                    write!(f, " (codes: {})", codes.join(", "))?;
+                }
+                if messages.is_empty() {
+                    write!(f, " but no error messages were found")
+                } else {
+                    write!(f, ": {}", messages.join("; "))
+                }
+            }
+            UpdateError::Malformed(msg) => write!(f, "Failed to parse Namecheap XML: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UpdateError::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for UpdateError {
+    fn from(e: reqwest::Error) -> Self {
+        // The request URL carries the DDNS password in its query string.
+        UpdateError::Transport(e.without_url())
+    }
+}
+
+/// No IP provider returned a usable address.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DetectError {
+    /// Every provider failed, returned an error status or an unusable address.
+    NoValidAddress,
+    /// Quorum mode: not enough providers agreed on an address.
+    NoQuorum {
+        /// The most reported address.
+        best: String,
+        votes: usize,
+        required: usize,
+    },
+}
+
+impl fmt::Display for DetectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DetectError::NoValidAddress => {
+                write!(f, "All IP providers failed or returned an invalid address")
+            }
+            DetectError::NoQuorum {
+                best,
+                votes,
+                required,
+            } => write!(
+                f,
+                "No IP quorum: best answer {best} had {votes} vote(s), {required} required"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DetectError {}
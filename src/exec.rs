@@ -0,0 +1,161 @@
+//! `exec:` provider: run a local command (an SSH call to the edge router, a
+//! vendor CLI, ...) and use its stdout as the address.
+
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+/// Split a command line into words, honouring single quotes, double quotes
+/// and backslash escapes. No shell is involved (the container image has
+/// none); use `sh -c '...'` explicitly for pipes.
+pub(crate) fn split_command(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => word.push(c),
+                            None => return Err("trailing backslash".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some(c) => word.push(c),
+                    None => return Err("trailing backslash".to_string()),
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    if words.is_empty() {
+        return Err("command is empty".to_string());
+    }
+    Ok(words)
+}
+
+/// Run `command_line`, killing it after `limit`, and return its stdout.
+/// A non-zero exit status is a failure, reported with the start of stderr.
+pub(crate) async fn run_command(command_line: &str, limit: Duration) -> io::Result<String> {
+    let words =
+        split_command(command_line).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    let child = Command::new(&words[0])
+        .args(&words[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let output = timeout(limit, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("command timed out after {}s", limit.as_secs_f32()),
+            )
+        })??;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let preview: String = stderr.trim().chars().take(200).collect();
+        return Err(io::Error::other(format!(
+            "command exited with {}: {}",
+            output.status, preview
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_command;
+
+    fn words(line: &str) -> Vec<String> {
+        split_command(line).unwrap()
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(
+            words("  dig +short  myip.opendns.com "),
+            ["dig", "+short", "myip.opendns.com"]
+        );
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(words(r"echo 'a\nb' 'c d'"), ["echo", r"a\nb", "c d"]);
+        assert_eq!(words(r"printf '\'"), ["printf", r"\"]);
+    }
+
+    #[test]
+    fn double_quotes_honour_backslash_escapes() {
+        assert_eq!(
+            words(r#"echo "say \"hi\"" "a\\b" "$x""#),
+            ["echo", r#"say "hi""#, r"a\b", "$x"]
+        );
+    }
+
+    #[test]
+    fn bare_backslash_escapes_the_next_character() {
+        assert_eq!(words(r"cat my\ file \'x"), ["cat", "my file", "'x"]);
+    }
+
+    #[test]
+    fn adjacent_segments_form_one_word() {
+        assert_eq!(words(r#"a'b c'"d e"f"#), ["ab cd ef"]);
+        assert_eq!(words("'' \"\""), ["", ""]);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for (line, error) in [
+            ("", "command is empty"),
+            ("   ", "command is empty"),
+            (r"echo a\", "trailing backslash"),
+            (r#"echo "a\"#, "trailing backslash"),
+            ("echo 'a", "unterminated single quote"),
+            ("echo \"a", "unterminated double quote"),
+        ] {
+            assert_eq!(split_command(line).unwrap_err(), error, "{line:?}");
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::detect::Family;
+use crate::persist;
+use crate::state::now_secs;
+
+/// Weight of the newest result in the moving averages.
+const SMOOTHING: f64 = 0.2;
+/// Consecutive failures before a provider is quarantined.
+const QUARANTINE_AFTER: u32 = 3;
+const QUARANTINE_BASE: Duration = Duration::from_secs(15 * 60);
+const QUARANTINE_MAX: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Track record of a single IP provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ProviderStats {
+    #[serde(default)]
+    pub successes: u64,
+    #[serde(default)]
+    pub failures: u64,
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// Moving average of successes (1.0) and failures (0.0), so a provider
+    /// that recovers climbs back within a few cycles.
+    #[serde(default = "full_reliability")]
+    pub reliability: f64,
+    /// Moving average of the latency of successful answers.
+    #[serde(default)]
+    pub latency_ms: Option<f64>,
+    /// Unix timestamp (seconds) until which the provider is only tried as a
+    /// last resort.
+    #[serde(default)]
+    pub quarantined_until: Option<u64>,
+}
+
+fn full_reliability() -> f64 {
+    1.0
+}
+
+impl Default for ProviderStats {
+    fn default() -> Self {
+        ProviderStats {
+            successes: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            reliability: full_reliability(),
+            latency_ms: None,
+            quarantined_until: None,
+        }
+    }
+}
+
+impl ProviderStats {
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantined_until
+            .is_some_and(|until| now_secs() < until)
+    }
+
+    /// Reliability in tenths; providers in the same bucket keep their
+    /// configured order so small fluctuations don't reshuffle the list.
+    fn rank(&self) -> u32 {
+        (self.reliability * 10.0).round() as u32
+    }
+}
+
+/// Result of asking one provider during a detection.
+#[derive(Debug, Clone)]
+pub(crate) struct Outcome {
+    pub provider: String,
+    pub ok: bool,
+    pub latency: Duration,
+}
+
+/// Per-provider health, used to try reliable providers first and to
+/// quarantine ones that keep failing. Kept separately per address family.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProviderHealth {
+    #[serde(default)]
+    ipv4: BTreeMap<String, ProviderStats>,
+    #[serde(default)]
+    ipv6: BTreeMap<String, ProviderStats>,
+    /// The order last returned by `reorder`, to log only changes.
+    #[serde(skip)]
+    last_order: BTreeMap<Family, Vec<String>>,
+}
+
+impl ProviderHealth {
+    /// Load saved health. A missing or unreadable file starts from scratch.
+    pub fn load(path: &Path) -> Self {
+        persist::load(path, "provider health")
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        persist::save(self, path)
+    }
+
+    fn family(&self, family: Family) -> &BTreeMap<String, ProviderStats> {
+        match family {
+            Family::V4 => &self.ipv4,
+            Family::V6 => &self.ipv6,
+        }
+    }
+
+    pub fn get(&self, family: Family, provider: &str) -> Option<&ProviderStats> {
+        self.family(family).get(provider)
+    }
+
+    /// `providers` with the most reliable first and quarantined ones moved
+    /// to the end; ties keep the configured order.
+    pub fn order(&self, family: Family, providers: &[String]) -> Vec<String> {
+        let stats = self.family(family);
+        let mut ordered: Vec<&String> = providers.iter().collect();
+        ordered.sort_by_key(|p| match stats.get(*p) {
+            Some(s) => (s.is_quarantined(), u32::MAX - s.rank()),
+            None => (false, u32::MAX - 10),
+        });
+        ordered.into_iter().cloned().collect()
+    }
+
+    /// Like `order`, but also reports whether the order differs from the
+    /// previous call (or, on the first call, from the configured order).
+    pub(crate) fn reorder(&mut self, family: Family, providers: &[String]) -> (Vec<String>, bool) {
+        let ordered = self.order(family, providers);
+        let previous = self.last_order.insert(family, ordered.clone());
+        let changed = match previous {
+            Some(previous) => previous != ordered,
+            None => ordered != providers,
+        };
+        (ordered, changed)
+    }
+
+    pub(crate) fn record(&mut self, family: Family, outcome: &Outcome) {
+        let stats = match family {
+            Family::V4 => &mut self.ipv4,
+            Family::V6 => &mut self.ipv6,
+        }
+        .entry(outcome.provider.clone())
+        .or_default();
+
+        let sample = if outcome.ok { 1.0 } else { 0.0 };
+        stats.reliability += SMOOTHING * (sample - stats.reliability);
+
+        if outcome.ok {
+            let ms = outcome.latency.as_secs_f64() * 1000.0;
+            stats.latency_ms = Some(match stats.latency_ms {
+                Some(avg) => avg + SMOOTHING * (ms - avg),
+                None => ms,
+            });
+            stats.successes += 1;
+            if stats.quarantined_until.take().is_some() {
+                info!(
+                    "Provider {} answered again, lifting its quarantine",
+                    outcome.provider
+                );
+            }
+            stats.consecutive_failures = 0;
+            return;
+        }
+
+        stats.failures += 1;
+        stats.consecutive_failures = stats.consecutive_failures.saturating_add(1);
+        if stats.consecutive_failures >= QUARANTINE_AFTER {
+            // Double the quarantine for every failure past the threshold.
+            let doublings = (stats.consecutive_failures - QUARANTINE_AFTER).min(8);
+            let period = (QUARANTINE_BASE * 2u32.pow(doublings)).min(QUARANTINE_MAX);
+            stats.quarantined_until = Some(now_secs() + period.as_secs());
+            warn!(
+                "Provider {} failed {} times in a row, quarantined for {}m",
+                outcome.provider,
+                stats.consecutive_failures,
+                period.as_secs() / 60
+            );
+        }
+    }
+
+    /// Reliability, totals, latency and quarantine of each provider, on a
+    /// single line (separated by `; `) so every log style stays one record
+    /// per line.
+    pub fn summary(&self, family: Family, providers: &[String]) -> String {
+        let mut out = String::new();
+        for p in providers {
+            let default = ProviderStats::default();
+            let s = self.get(family, p).unwrap_or(&default);
+            if !out.is_empty() {
+                out.push_str("; ");
+            }
+            let _ = write!(
+                out,
+                "{}: score={:.2} ok={}/{}",
+                p,
+                s.reliability,
+                s.successes,
+                s.successes + s.failures
+            );
+            if let Some(ms) = s.latency_ms {
+                let _ = write!(out, " latency={ms:.0}ms");
+            }
+            if s.is_quarantined() {
+                let _ = write!(out, " quarantined");
+            }
+        }
+        out
+    }
+}
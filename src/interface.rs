@@ -0,0 +1,56 @@
+use log::{debug, warn};
+use std::net::IpAddr;
+
+use crate::detect::{is_public_ip, DetectOptions};
+
+/// Address of the local interface `name` in the requested family, for hosts
+/// whose WAN address sits directly on an interface (bridge-mode modems).
+/// Public addresses are preferred; private ones are only used when allowed.
+pub(crate) fn interface_address(name: &str, options: DetectOptions) -> Option<String> {
+    let interfaces = match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces,
+        Err(e) => {
+            warn!("Failed to list network interfaces: {}", e);
+            return None;
+        }
+    };
+
+    let mut found = false;
+    let mut candidates: Vec<IpAddr> = Vec::new();
+    for iface in interfaces.iter().filter(|i| i.name == name) {
+        found = true;
+        let ip = iface.ip();
+        if options.family.matches(&ip) {
+            candidates.push(ip);
+        }
+    }
+
+    if !found {
+        warn!("Interface {} not found", name);
+        return None;
+    }
+
+    if let Some(ip) = candidates.iter().find(|ip| is_public_ip(**ip)) {
+        return Some(ip.to_string());
+    }
+    if options.allow_private {
+        if let Some(ip) = candidates.first() {
+            return Some(ip.to_string());
+        }
+    }
+
+    for ip in &candidates {
+        debug!("Skipping non-public address {} on interface {}", ip, name);
+    }
+    warn!(
+        "Interface {} has no public {} address{}",
+        name,
+        options.family,
+        if candidates.is_empty() {
+            ""
+        } else {
+            " (set NC_ALLOW_PRIVATE_IP=1 to use a private one)"
+        }
+    );
+    None
+}
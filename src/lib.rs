@@ -0,0 +1,49 @@
+//! Tiny Namecheap Dynamic DNS updater.
+//!
+//! The `namecheap-ddns` binary is a thin wrapper around this library; the
+//! same pieces can be embedded in other tooling:
+//!
+//! ```no_run
+//! use namecheap_ddns::{detect_ip, Client};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::new()?;
+//! let providers = vec!["https://api.ipify.org".to_string()];
+//! let ip = detect_ip(client.http(), &providers).await?;
+//! client.update("@", "example.com", "ddns-password", &ip).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod backoff;
+mod classify;
+mod client;
+mod config;
+mod detect;
+mod dns;
+mod error;
+mod exec;
+mod health;
+mod interface;
+mod persist;
+mod provider;
+pub mod redact;
+mod response;
+mod router;
+mod state;
+mod stun;
+mod verify;
+
+pub use backoff::Backoff;
+pub use classify::{classify_response, ErrorClass, RetryPolicy};
+pub use client::{http_client_for, Client};
+pub use config::{env_flag, Config, ConfigError, DomainConfig};
+pub use detect::{
+    detect_ip, detect_ip_scored, detect_ip_with, is_public_ip, is_public_ipv4, is_public_ipv6,
+    DetectOptions, DetectStrategy, Family,
+};
+pub use error::{DetectError, UpdateError};
+pub use health::{ProviderHealth, ProviderStats};
+pub use response::{parse_namecheap_response, NamecheapError, NamecheapResponse, ResponseEntry};
+pub use state::{HostState, State};
+pub use verify::{fqdn, published_addresses, wait_for_propagation, Propagation};
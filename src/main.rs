@@ -1,334 +1,497 @@
-use log::{error, info, trace, warn};
-use quick_xml::{events::Event, Reader};
-use reqwest::{Client, Url};
+use log::{error, info, warn};
+use namecheap_ddns::{
+    detect_ip_scored, env_flag, fqdn, http_client_for, published_addresses, redact,
+    wait_for_propagation, Client, Config, DomainConfig, Family, ProviderHealth, RetryPolicy, State,
+    UpdateError,
+};
 use std::env;
-use std::fs;
-use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tokio::time::sleep;
-
-struct Config {
-    domain: String,
-    password: String,
-    hosts: Vec<String>,
-    interval_secs: u64,
-    ip_providers: Vec<String>,
-}
+use tokio::time::{sleep_until, Instant};
 
-impl Config {
-    fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
-        let domain = env::var("NC_DOMAIN").expect("NC_DOMAIN env var missing");
-        let password = env::var("NC_PASSWORD").expect("NC_PASSWORD env var missing");
-        let hosts_raw = env::var("NC_HOSTS").expect("NC_HOSTS env var missing");
-
-        let interval_secs: u64 = env::var("NC_INTERVAL_SECONDS")
-            .unwrap_or_else(|_| "300".to_string())
-            .parse()
-            .expect("NC_INTERVAL_SECONDS must be an integer");
-
-        let ip_providers: Vec<String> = env::var("NC_IP_PROVIDERS")
-            .unwrap_or_else(|_| {
-                "https://ifconfig.me/ip,https://ipv4.icanhazip.com,https://api.ipify.org"
-                    .to_string()
-            })
-            .split(',')
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
+fn init_logging() {
+    use env_logger::Env;
+    use std::io::Write;
 
-        let hosts: Vec<String> = hosts_raw
-            .split(',')
-            .map(|x| x.trim().to_string())
-            .filter(|x| !x.is_empty())
-            .collect();
+    let style = std::env::var("LOG_STYLE")
+        .unwrap_or_else(|_| "default".to_string())
+        .to_lowercase();
 
-        Ok(Config {
-            domain,
-            password,
-            hosts,
-            interval_secs,
-            ip_providers,
-        })
-    }
-}
+    let mut builder = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
 
-fn looks_like_ipv4(s: &str) -> bool {
-    s.parse::<IpAddr>()
-        .map(|ip| ip.is_ipv4())
-        .unwrap_or(false)
-}
+    match style.as_str() {
+        "compact" => {
+            builder
+                .format_timestamp_secs()
+                .format(|buf, record| writeln!(buf, "[{}] {}", record.level(), record.args()));
+        }
 
-async fn get_current_ip(
-    client: &Client,
-    providers: &[String],
-) -> Result<String, Box<dyn std::error::Error>> {
-    for p in providers {
-        if p.is_empty() {
-            continue;
+        "raw" => {
+            builder.format(|buf, record| writeln!(buf, "{}", record.args()));
         }
-        info!("Trying IP provider: {}", p);
 
-        match client.get(p).send().await {
-            Ok(resp) if resp.status().is_success() => {
-                let text = resp.text().await?;
-                let ip = text.trim();
+        "json" => {
+            builder.format(|buf, record| {
+                let ts = buf.timestamp();
+                // Escaped, so quotes or newlines in a message can't break
+                // the one-object-per-line output.
+                let msg = serde_json::to_string(&record.args().to_string())?;
+                writeln!(
+                    buf,
+                    "{{\"ts\":\"{}\",\"level\":\"{}\",\"msg\":{}}}",
+                    ts,
+                    record.level(),
+                    msg
+                )
+            });
+        }
 
-                if looks_like_ipv4(ip) {
-                    return Ok(ip.to_string());
-                } else {
-                    let preview = &text[..text.len().min(80)];
-                    warn!("Provider {} returned non-IPv4: {:?}", p, preview);
-                }
-            }
-            Ok(resp) => {
-                warn!("Provider {} returned status {}", p, resp.status());
-            }
-            Err(e) => {
-                warn!("Provider {} failed: {}", p, e);
-            }
+        _ => {
+            // "default": normal env_logger formatting
         }
     }
-    Err("All IP providers failed or returned invalid IPv4".into())
-}
 
-/// Parse Namecheap's XML DDNS response.
-/// Returns Ok(()) if ErrCount == 0.
-/// Returns Err(message) if ErrCount > 0 or XML is malformed.
-fn parse_namecheap_response(xml: &str) -> Result<(), String> {
-    let mut reader = Reader::from_str(xml);
-    // quick-xml 0.36: configure trimming via config_mut()
-    reader.config_mut().trim_text(true);
-
-    let mut buf = Vec::new();
-    let mut current_tag: Option<String> = None;
-
-    let mut err_count: Option<u32> = None;
-    let mut errors: Vec<String> = Vec::new();
-    let mut descriptions: Vec<String> = Vec::new();
-    let mut response_strings: Vec<String> = Vec::new();
+    let logger = builder.build();
+    let max_level = logger.filter();
+    redact::install_logger(logger, max_level);
+}
 
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                current_tag = Some(String::from_utf8_lossy(e.name().as_ref()).to_string());
-            }
-            Ok(Event::Text(e)) => {
-                if let Some(tag) = &current_tag {
-                    let text = e.unescape().unwrap_or_default().trim().to_string();
-                    if text.is_empty() {
-                        buf.clear();
-                        continue;
-                    }
+/// Exit code for invalid command line arguments or configuration.
+const EXIT_CONFIG: i32 = 2;
+/// `--once`: no IP provider returned a usable address.
+const EXIT_DETECT_FAILED: i32 = 3;
+/// `--once`: Namecheap rejected at least one update (ErrCount > 0).
+const EXIT_REJECTED: i32 = 4;
+/// `--once`: at least one update could not be delivered (network, HTTP status).
+const EXIT_UNREACHABLE: i32 = 5;
+
+/// Command line flags. Everything else is configured via env vars or the config file.
+struct Cli {
+    config: Option<PathBuf>,
+    /// Run a single detect/compare/update cycle and exit (`--once` or `NC_ONCE=1`).
+    once: bool,
+}
 
-                    match tag.as_str() {
-                        "ErrCount" => {
-                            if let Ok(n) = text.parse::<u32>() {
-                                err_count = Some(n);
-                            }
-                        }
-                        // <Err1>, <Err2>, ...
-                        t if t.starts_with("Err") => {
-                            errors.push(text);
-                        }
-                        "Description" => {
-                            descriptions.push(text);
-                        }
-                        "ResponseString" => {
-                            response_strings.push(text);
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            Ok(Event::End(_)) => {
-                current_tag = None;
-            }
-            Ok(Event::Eof) => break,
-            Err(e) => {
-                return Err(format!("Failed to parse Namecheap XML: {e}"));
+impl Cli {
+    fn parse() -> Result<Self, String> {
+        let mut cli = Cli {
+            config: None,
+            once: env_flag("NC_ONCE").map_err(|e| e.to_string())?,
+        };
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            if arg == "--once" {
+                cli.once = true;
+            } else if arg == "--config" {
+                let path = args.next().ok_or("--config requires a path")?;
+                cli.config = Some(PathBuf::from(path));
+            } else if let Some(path) = arg.strip_prefix("--config=") {
+                cli.config = Some(PathBuf::from(path));
+            } else {
+                return Err(format!("Unknown argument: {arg}"));
             }
-            _ => {}
         }
 
-        buf.clear();
+        Ok(cli)
     }
+}
 
-    let count = err_count.unwrap_or(0);
+/// What happened during one detect/compare/update cycle.
+#[derive(Default)]
+struct CycleReport {
+    detect_failed: bool,
+    updated: usize,
+    rejected: usize,
+    unreachable: usize,
+    /// Hosts skipped while waiting out a retry delay, split like the
+    /// failures that put them on hold.
+    held_rejected: usize,
+    held_unreachable: usize,
+}
 
-    // Success path: ErrCount == 0 (or missing)
-    if count == 0 {
-        return Ok(());
+impl CycleReport {
+    fn attempted(&self) -> bool {
+        self.updated + self.rejected + self.unreachable > 0
     }
 
-    // Error path: ErrCount > 0 – collect any messages we can
-    let mut messages = Vec::new();
-    messages.extend(errors);
-    messages.extend(descriptions);
-    messages.extend(response_strings);
-
-    if messages.is_empty() {
-        Err(format!(
-            "Namecheap reported ErrCount={} but no error messages were found",
-            count
-        ))
-    } else {
-        Err(messages.join("; "))
+    /// Exit status for `--once`; a rejection wins over a delivery failure
+    /// because it needs a human to fix the configuration.
+    fn exit_code(&self) -> i32 {
+        if self.detect_failed {
+            EXIT_DETECT_FAILED
+        } else if self.rejected + self.held_rejected > 0 {
+            EXIT_REJECTED
+        } else if self.unreachable + self.held_unreachable > 0 {
+            EXIT_UNREACHABLE
+        } else {
+            0
+        }
     }
 }
 
-async fn update_namecheap(
-    client: &Client,
+/// Whether the nameservers publish something other than `ip` for this host,
+/// e.g. after an edit in the dashboard or a lost state file. Lookup failures
+/// are logged and trust the state file.
+async fn record_differs(
+    config: &Config,
     host: &str,
     domain: &str,
-    password: &str,
+    family: Family,
     ip: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut url = Url::parse("https://dynamicdns.park-your-domain.com/update")?;
-    url.query_pairs_mut()
-        .append_pair("host", host)
-        .append_pair("domain", domain)
-        .append_pair("password", password)
-        .append_pair("ip", ip);
-
-    let resp = client.get(url).send().await?;
-    let status = resp.status();
-    let body = resp.text().await?;
-
-    match parse_namecheap_response(&body) {
-        Ok(()) => {
-            let preview = &body[..body.len().min(160)];
-            info!(
-                "Namecheap DDNS update succeeded: host={}, status={} {}, body_preview={:?}",
-                host,
-                status.as_u16(),
-                status.canonical_reason().unwrap_or(""),
-                preview,
+) -> bool {
+    let name = fqdn(host, domain);
+    let kind = family.record_type();
+    match published_addresses(&name, family, &config.dns_servers).await {
+        Ok(published) if published.iter().any(|p| p.to_string() == ip) => false,
+        Ok(published) => {
+            let shown: Vec<String> = published.iter().map(|p| p.to_string()).collect();
+            warn!(
+                "Published {} record for {} is [{}], expected {}; updating",
+                kind,
+                name,
+                shown.join(", "),
+                ip
             );
-            trace!("Namecheap full XML response: {}", body);
+            true
+        }
+        Err(e) => {
+            warn!("Could not verify {} record for {}: {}", kind, name, e);
+            false
         }
-        Err(parse_err) => {
-            error!(
-                "Namecheap DDNS update FAILED for host={}: {} (status={} {}) \
-                 → This usually means wrong domain/host/password.",
-                host,
-                parse_err,
-                status.as_u16(),
-                status.canonical_reason().unwrap_or(""),
+    }
+}
+
+/// Push `ip` to every `family` host of `domain` that is stale or failing,
+/// or (with `verify_dns`) whose published record differs from `ip`.
+async fn update_domain(
+    client: &Client,
+    config: &Config,
+    domain: &DomainConfig,
+    family: Family,
+    ip: &str,
+    state: &mut State,
+    report: &mut CycleReport,
+) {
+    let hosts = domain.hosts_for(family);
+    if hosts.is_empty() {
+        return;
+    }
+    let kind = family.record_type();
+
+    let mut stale: Vec<&String> = hosts
+        .iter()
+        .filter(|host| state.needs_update(&domain.domain, host, family, ip))
+        .collect();
+
+    if config.verify_dns {
+        for host in hosts {
+            if stale.contains(&host) || state.is_held(&domain.domain, host, family, ip) {
+                continue;
+            }
+            if record_differs(config, host, &domain.domain, family, ip).await {
+                stale.push(host);
+            }
+        }
+    }
+
+    for host in hosts {
+        if state.is_held(&domain.domain, host, family, ip) {
+            match state.get(&domain.domain, host, family) {
+                Some(h) if h.rejected => report.held_rejected += 1,
+                _ => report.held_unreachable += 1,
+            }
+            info!(
+                "Host {} ({}) of {} is waiting out a retry delay, skipping.",
+                host, kind, domain.domain
             );
-            // Only dump full XML at trace level so normal logs stay clean
-            trace!("Namecheap full XML error response: {}", body);
         }
     }
 
-    Ok(())
-}
+    if stale.is_empty() {
+        info!(
+            "No stale {} hosts for {}, skipping updates.",
+            kind, domain.domain
+        );
+        return;
+    }
 
-fn init_logging() {
-    use env_logger::Env;
-    use std::io::Write;
+    let mut updated: Vec<String> = Vec::new();
+    for host in stale {
+        let what = format!("updating host {} ({}) of {}", host, kind, domain.domain);
+        let result = config
+            .retry
+            .retry(&what, || {
+                client.update(host, &domain.domain, &domain.password, ip)
+            })
+            .await;
+        match result {
+            Ok(response) => {
+                if let Some(applied) = response.ip.filter(|a| a.to_string() != ip) {
+                    warn!(
+                        "Namecheap applied {} instead of {} for host={}, domain={}",
+                        applied, ip, host, domain.domain
+                    );
+                }
+                report.updated += 1;
+                updated.push(fqdn(host, &domain.domain));
+                state.record_success(&domain.domain, host, family, ip);
+            }
+            Err(e) => {
+                let class = e.class();
+                if let UpdateError::Namecheap(_) = e {
+                    report.rejected += 1;
+                    error!(
+                        "Namecheap DDNS update FAILED for host={}, domain={}: {} [{}] → {}",
+                        host,
+                        domain.domain,
+                        e,
+                        class,
+                        class.hint()
+                    );
+                } else {
+                    report.unreachable += 1;
+                    error!(
+                        "Error updating host {} of {}: {} [{}] → {}",
+                        host,
+                        domain.domain,
+                        e,
+                        class,
+                        class.hint()
+                    );
+                }
 
-    let style = std::env::var("LOG_STYLE")
-        .unwrap_or_else(|_| "default".to_string())
-        .to_lowercase();
+                let retry_in = match class.retry_policy() {
+                    RetryPolicy::NextCycle => None,
+                    RetryPolicy::After(delay) => {
+                        warn!(
+                            "Not retrying host {} of {} for {}s",
+                            host,
+                            domain.domain,
+                            delay.as_secs()
+                        );
+                        Some(delay)
+                    }
+                };
+                state.record_failure(&domain.domain, host, family, ip, &e, retry_in);
+            }
+        }
+    }
 
-    let mut builder = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
+    if let Some(timeout) = config.propagation_timeout {
+        if !updated.is_empty() {
+            check_propagation(config, &updated, family, ip, timeout).await;
+        }
+    }
+}
 
-    match style.as_str() {
-        "compact" => {
-            builder
-                .format_timestamp_secs()
-                .format(|buf, record| {
-                    writeln!(buf, "[{}] {}", record.level(), record.args())
-                });
+/// Wait for freshly updated records to show up on the nameservers and warn
+/// about any that don't within `timeout`.
+async fn check_propagation(
+    config: &Config,
+    names: &[String],
+    family: Family,
+    ip: &str,
+    timeout: Duration,
+) {
+    let kind = family.record_type();
+    for record in wait_for_propagation(names, family, ip, &config.dns_servers, timeout).await {
+        match record.verified_after {
+            Some(after) => info!(
+                "Verified {} record for {} = {} on the nameservers after {:.1}s",
+                kind,
+                record.name,
+                ip,
+                after.as_secs_f64()
+            ),
+            None => {
+                let seen: Vec<String> = record.last_seen.iter().map(|p| p.to_string()).collect();
+                warn!(
+                    "Update of {} record for {} to {} not visible on the nameservers after {}s (still publishing [{}])",
+                    kind,
+                    record.name,
+                    ip,
+                    timeout.as_secs(),
+                    seen.join(", ")
+                );
+            }
         }
+    }
+}
 
-        "raw" => {
-            builder.format(|buf, record| {
-                writeln!(buf, "{}", record.args())
-            });
+/// HTTP clients pinned to IPv4 and IPv6 for the IP providers, so a
+/// dual-stack provider reports the address of the family we asked about,
+/// plus the providers' health.
+struct Detectors {
+    v4: reqwest::Client,
+    v6: reqwest::Client,
+    health: ProviderHealth,
+}
+
+impl Detectors {
+    fn new(config: &Config) -> Result<Self, reqwest::Error> {
+        Ok(Detectors {
+            v4: http_client_for(Family::V4)?,
+            v6: http_client_for(Family::V6)?,
+            health: config
+                .provider_health_path
+                .as_deref()
+                .map(ProviderHealth::load)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Detect the current IPv4 (and IPv6, if any due domain has AAAA hosts)
+/// once and update every domain in `due`.
+async fn run_cycle(
+    client: &Client,
+    detectors: &mut Detectors,
+    config: &Config,
+    due: &[usize],
+    state: &mut State,
+    state_path: &Path,
+) -> CycleReport {
+    let mut report = CycleReport::default();
+
+    for family in [Family::V4, Family::V6] {
+        let wanted: Vec<&DomainConfig> = due
+            .iter()
+            .map(|&i| &config.domains[i])
+            .filter(|d| !d.hosts_for(family).is_empty())
+            .collect();
+        if wanted.is_empty() {
+            continue;
         }
 
-        "json" => {
-            builder.format(|buf, record| {
-                let ts = buf.timestamp();
-                writeln!(
-                    buf,
-                    "{{\"ts\":\"{}\",\"level\":\"{}\",\"msg\":\"{}\"}}",
-                    ts,
-                    record.level(),
-                    record.args()
-                )
-            });
+        let (http, providers, options) = match family {
+            Family::V4 => (&detectors.v4, &config.ip_providers, &config.detect),
+            Family::V6 => (&detectors.v6, &config.ip6_providers, &config.detect6),
+        };
+        let detected = detect_ip_scored(http, providers, options, &mut detectors.health).await;
+        let current_ip = match detected {
+            Ok(ip) => ip,
+            Err(e) => {
+                warn!("Failed to detect {}: {}", family, e);
+                report.detect_failed = true;
+                continue;
+            }
+        };
+
+        info!("Current {}: {}", family, current_ip);
+
+        for domain in wanted {
+            update_domain(
+                client,
+                config,
+                domain,
+                family,
+                &current_ip,
+                state,
+                &mut report,
+            )
+            .await;
         }
+    }
 
-        "default" | _ => {
-            // normal env_logger formatting
+    if report.attempted() {
+        if let Err(e) = state.save(state_path) {
+            warn!("Failed to write state file {}: {}", state_path.display(), e);
+        }
+    }
+    if let Some(path) = &config.provider_health_path {
+        if let Err(e) = detectors.health.save(path) {
+            warn!("Failed to write provider health {}: {}", path.display(), e);
         }
     }
 
-    builder.init();
+    report
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     init_logging();
 
-    let config = Config::from_env()?;
+    let cli = match Cli::parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(EXIT_CONFIG);
+        }
+    };
 
-    info!(
-        "Starting namecheap-ddns: domain={}, hosts={:?}, interval={}s",
-        config.domain, config.hosts, config.interval_secs
-    );
+    let config = match Config::load(cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            for problem in &e.problems {
+                error!("Config error: {}", problem);
+            }
+            std::process::exit(EXIT_CONFIG);
+        }
+    };
 
-    let client = Client::builder()
-        .user_agent("namecheap-ddns-rust/0.1")
-        .timeout(Duration::from_secs(10))
-        .build()?;
+    for d in &config.domains {
+        redact::register_secret(&d.password);
+    }
 
-    let cache_path = "/data/last_ip";
+    for d in &config.domains {
+        info!(
+            "Starting namecheap-ddns: domain={}, hosts={:?}, aaaa_hosts={:?}, interval={}s",
+            d.domain, d.hosts, d.aaaa_hosts, d.interval_secs
+        );
+    }
 
-    loop {
-        let current_ip = match get_current_ip(&client, &config.ip_providers).await {
-            Ok(ip) => ip,
-            Err(e) => {
-                warn!("Failed to detect IP: {}", e);
-                sleep(Duration::from_secs(config.interval_secs)).await;
-                continue;
-            }
-        };
+    let client = Client::new()?.with_endpoint(config.endpoint.clone());
+    let mut detectors = Detectors::new(&config)?;
+
+    let state_path = config.state_path.as_path();
+    let mut state = State::load(state_path);
+
+    if cli.once {
+        let all: Vec<usize> = (0..config.domains.len()).collect();
+        let report = run_cycle(
+            &client,
+            &mut detectors,
+            &config,
+            &all,
+            &mut state,
+            state_path,
+        )
+        .await;
+        std::process::exit(report.exit_code());
+    }
 
-        info!("Current IPv4: {}", current_ip);
+    // A daemon restart usually follows a config fix, so don't keep waiting
+    // out delays caused by the old configuration. In --once mode every run
+    // is a "restart", so the delays are honoured there.
+    state.clear_retry_delays();
 
-        let last_ip = if Path::new(cache_path).exists() {
-            fs::read_to_string(cache_path).unwrap_or_default()
-        } else {
-            String::new()
-        };
-        let last_ip = last_ip.trim();
+    // Each domain runs on its own interval; one IP detection serves every
+    // domain that is due in a given tick.
+    let mut next_due: Vec<Instant> = vec![Instant::now(); config.domains.len()];
 
-        if last_ip == current_ip {
-            info!("IP unchanged, skipping updates.");
-        } else {
-            for host in &config.hosts {
-                if let Err(e) = update_namecheap(
-                    &client,
-                    host,
-                    &config.domain,
-                    &config.password,
-                    &current_ip,
-                )
-                .await
-                {
-                    error!("Error updating host {}: {}", host, e);
-                }
-            }
+    loop {
+        let now = Instant::now();
+        let due: Vec<usize> = (0..config.domains.len())
+            .filter(|&i| next_due[i] <= now)
+            .collect();
 
-            if let Err(e) = fs::write(cache_path, &current_ip) {
-                warn!("Failed to write cache: {}", e);
-            }
+        for &i in &due {
+            next_due[i] = now + Duration::from_secs(config.domains[i].interval_secs);
         }
 
-        sleep(Duration::from_secs(config.interval_secs)).await;
+        run_cycle(
+            &client,
+            &mut detectors,
+            &config,
+            &due,
+            &mut state,
+            state_path,
+        )
+        .await;
+
+        if let Some(&at) = next_due.iter().min() {
+            sleep_until(at).await;
+        }
     }
-}
\ No newline at end of file
+}
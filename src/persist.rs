@@ -0,0 +1,33 @@
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Load a JSON file written by `save`. A missing file yields the default;
+/// an unreadable or corrupt one is logged and treated as missing, so the
+/// daemon starts from scratch rather than refusing to run. `what` names the
+/// file in log lines.
+pub(crate) fn load<T: DeserializeOwned + Default>(path: &Path, what: &str) -> T {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return T::default(),
+        Err(e) => {
+            warn!("Failed to read {} {}: {}", what, path.display(), e);
+            return T::default();
+        }
+    };
+
+    serde_json::from_str(&raw).unwrap_or_else(|e| {
+        warn!("Ignoring corrupt {} {}: {}", what, path.display(), e);
+        T::default()
+    })
+}
+
+/// Write `value` as pretty JSON atomically (temp file + rename).
+pub(crate) fn save<T: Serialize>(value: &T, path: &Path) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(value)?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(&tmp, path)
+}
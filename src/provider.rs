@@ -0,0 +1,229 @@
+use regex_lite::Regex;
+use std::borrow::Cow;
+
+use crate::config::is_http_url;
+use crate::exec::split_command;
+
+/// How the address is pulled out of an HTTP provider's body, selected with
+/// a `#plain`, `#json:<pointer>` or `#regex:<pattern>` suffix on the URL.
+#[derive(Debug, Clone)]
+pub(crate) enum Extract {
+    /// The whole body is the address (the default).
+    Plain,
+    /// JSON pointer into the body, e.g. `/ip` for `{"ip":"..."}`.
+    JsonPointer(String),
+    /// First capture group (or the whole match) of a regex, for HTML status
+    /// pages and other free-form bodies.
+    Regex(Regex),
+}
+
+impl Extract {
+    /// Split the extraction suffix off an HTTP provider entry.
+    fn split(spec: &str) -> Result<(&str, Extract), String> {
+        if let Some(url) = spec.strip_suffix("#plain") {
+            return Ok((url, Extract::Plain));
+        }
+        if let Some((url, pointer)) = spec.split_once("#json:") {
+            if !pointer.is_empty() && !pointer.starts_with('/') {
+                return Err(format!(
+                    "IP provider {spec:?}: JSON pointer must start with '/'"
+                ));
+            }
+            return Ok((url, Extract::JsonPointer(pointer.to_string())));
+        }
+        if let Some((url, pattern)) = spec.split_once("#regex:") {
+            return match Regex::new(pattern) {
+                Ok(re) => Ok((url, Extract::Regex(re))),
+                Err(e) => Err(format!("IP provider {spec:?}: invalid regex: {e}")),
+            };
+        }
+        Ok((spec, Extract::Plain))
+    }
+
+    /// The address text within `body`, or why it could not be found.
+    pub(crate) fn apply<'t>(&self, body: &'t str) -> Result<Cow<'t, str>, String> {
+        match self {
+            Extract::Plain => Ok(Cow::Borrowed(body)),
+            Extract::JsonPointer(pointer) => {
+                let json: serde_json::Value =
+                    serde_json::from_str(body).map_err(|e| format!("invalid JSON: {e}"))?;
+                match json.pointer(pointer) {
+                    Some(serde_json::Value::String(s)) => Ok(Cow::Owned(s.clone())),
+                    Some(other) => Ok(Cow::Owned(other.to_string())),
+                    None => Err(format!("no value at JSON pointer {pointer:?}")),
+                }
+            }
+            Extract::Regex(re) => {
+                let caps = re
+                    .captures(body)
+                    .ok_or_else(|| format!("regex {:?} did not match", re.as_str()))?;
+                let m = caps.get(1).or_else(|| caps.get(0)).expect("match exists");
+                Ok(Cow::Borrowed(m.as_str()))
+            }
+        }
+    }
+}
+
+/// Where an IP provider entry gets its answer from, as spelled in
+/// `NC_IP_PROVIDERS`: an http(s) URL or `kind:argument`.
+#[derive(Debug, Clone)]
+pub(crate) enum Provider<'a> {
+    /// GET the URL and read the address from the body.
+    Http { url: &'a str, extract: Extract },
+    /// Read the address of a local network interface, e.g. `interface:eth0`.
+    Interface(&'a str),
+    /// Query `name` at nameserver `server`, which answers with the address
+    /// the query came from: `dns:myip.opendns.com@resolver1.opendns.com`,
+    /// or with `txt`, `dns-txt:o-o.myaddr.l.google.com@ns1.google.com`.
+    Dns {
+        name: &'a str,
+        server: &'a str,
+        txt: bool,
+    },
+    /// STUN Binding Request to `host[:port]`, e.g. `stun:stun.l.google.com:19302`.
+    Stun(&'a str),
+    /// UPnP IGD `GetExternalIPAddress`: `upnp:` discovers the router via
+    /// SSDP, `upnp:<description URL>` skips discovery.
+    Upnp(Option<&'a str>),
+    /// NAT-PMP external address request: `natpmp:` asks the default
+    /// gateway, `natpmp:<host[:port]>` a specific one.
+    NatPmp(Option<&'a str>),
+    /// Run a command and read the address from its stdout, e.g.
+    /// `exec:ssh router "show ip wan"`.
+    Exec(&'a str),
+}
+
+impl<'a> Provider<'a> {
+    /// Parse a provider entry, describing what is wrong with it on failure.
+    pub(crate) fn parse(spec: &'a str) -> Result<Self, String> {
+        if let Some(name) = spec.strip_prefix("interface:") {
+            return if name.is_empty() {
+                Err(format!("IP provider {spec:?} is missing an interface name"))
+            } else {
+                Ok(Provider::Interface(name))
+            };
+        }
+        if let Some(url) = spec.strip_prefix("upnp:") {
+            return match url {
+                "" => Ok(Provider::Upnp(None)),
+                url if is_http_url(url) => Ok(Provider::Upnp(Some(url))),
+                _ => Err(format!(
+                    "IP provider {spec:?} must be upnp: or upnp:<description URL>"
+                )),
+            };
+        }
+        if let Some(command) = spec.strip_prefix("exec:") {
+            return match split_command(command) {
+                Ok(_) => Ok(Provider::Exec(command)),
+                Err(e) => Err(format!("IP provider {spec:?}: {e}")),
+            };
+        }
+        if let Some(gateway) = spec.strip_prefix("natpmp:") {
+            return Ok(Provider::NatPmp((!gateway.is_empty()).then_some(gateway)));
+        }
+        if let Some(server) = spec.strip_prefix("stun:") {
+            return if server.is_empty() {
+                Err(format!("IP provider {spec:?} is missing a STUN server"))
+            } else {
+                Ok(Provider::Stun(server))
+            };
+        }
+        for (prefix, txt) in [("dns:", false), ("dns-txt:", true)] {
+            if let Some(rest) = spec.strip_prefix(prefix) {
+                return match rest.split_once('@') {
+                    Some((name, server)) if !name.is_empty() && !server.is_empty() => {
+                        Ok(Provider::Dns { name, server, txt })
+                    }
+                    _ => Err(format!(
+                        "IP provider {spec:?} must look like {prefix}<name>@<nameserver>"
+                    )),
+                };
+            }
+        }
+        let (url, extract) = Extract::split(spec)?;
+        if is_http_url(url) {
+            Ok(Provider::Http { url, extract })
+        } else {
+            Err(format!(
+                "IP provider {spec:?} is not a valid http(s) URL, interface:, dns:, dns-txt:, stun:, upnp:, natpmp: or exec: provider"
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Extract;
+
+    fn split(spec: &str) -> (&str, Extract) {
+        Extract::split(spec).unwrap()
+    }
+
+    #[test]
+    fn plain_suffix_is_stripped_and_other_fragments_kept() {
+        for spec in ["https://api.ipify.org", "https://api.ipify.org#plain"] {
+            let (url, extract) = split(spec);
+            assert_eq!(url, "https://api.ipify.org");
+            assert!(matches!(extract, Extract::Plain));
+        }
+        // Other fragments are part of the URL.
+        let (url, extract) = split("https://example.com/ip#section");
+        assert_eq!(url, "https://example.com/ip#section");
+        assert!(matches!(extract, Extract::Plain));
+    }
+
+    #[test]
+    fn json_pointer_reads_strings_and_other_values() {
+        let (url, extract) = split("https://ipinfo.io/json#json:/ip");
+        assert_eq!(url, "https://ipinfo.io/json");
+        let Extract::JsonPointer(pointer) = &extract else {
+            panic!("{extract:?}");
+        };
+        assert_eq!(pointer, "/ip");
+        assert_eq!(
+            extract.apply(r#"{"ip": "93.184.216.34"}"#).unwrap(),
+            "93.184.216.34"
+        );
+
+        let (_, nested) = split("https://x/#json:/data/0/addr");
+        assert_eq!(nested.apply(r#"{"data": [{"addr": 7}]}"#).unwrap(), "7");
+        assert!(nested.apply("{}").unwrap_err().contains("no value"));
+        assert!(nested
+            .apply("not json")
+            .unwrap_err()
+            .contains("invalid JSON"));
+
+        // The empty pointer is the whole document.
+        let (_, whole) = split("https://x/#json:");
+        assert_eq!(whole.apply(r#""93.184.216.34""#).unwrap(), "93.184.216.34");
+    }
+
+    #[test]
+    fn regex_uses_the_first_group_or_the_whole_match() {
+        let (url, grouped) = split(r"https://x/trace#regex:ip=(\S+)");
+        assert_eq!(url, "https://x/trace");
+        assert_eq!(
+            grouped.apply("h=x\nip=93.184.216.34\nts=1").unwrap(),
+            "93.184.216.34"
+        );
+
+        let (_, whole) = split(r"https://x/#regex:\d+\.\d+\.\d+\.\d+");
+        assert_eq!(
+            whole.apply("Current IP: 93.184.216.34").unwrap(),
+            "93.184.216.34"
+        );
+        assert!(whole
+            .apply("no address")
+            .unwrap_err()
+            .contains("did not match"));
+    }
+
+    #[test]
+    fn rejects_bad_suffixes() {
+        let err = Extract::split("https://x/#json:ip").unwrap_err();
+        assert!(err.contains("must start with '/'"), "{err}");
+
+        let err = Extract::split("https://x/#regex:(").unwrap_err();
+        assert!(err.contains("invalid regex"), "{err}");
+    }
+}
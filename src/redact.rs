@@ -0,0 +1,185 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::borrow::Cow;
+use std::sync::RwLock;
+
+const MASK: &str = "***";
+
+/// Secrets that must never show up in logs, in both their raw and
+/// URL-encoded forms.
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// `application/x-www-form-urlencoded` encoding, as used by reqwest for query
+/// strings, so we also catch the password inside logged URLs. `space` is `+`
+/// for query strings and `%20` for paths.
+fn urlencode(s: &str, space: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                out.push(b as char)
+            }
+            b' ' => out.push_str(space),
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+/// Register a secret to be masked from now on. Empty strings are ignored.
+pub fn register_secret(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    let mut secrets = SECRETS.write().unwrap_or_else(|e| e.into_inner());
+    for form in [
+        secret.to_string(),
+        urlencode(secret, "+"),
+        urlencode(secret, "%20"),
+    ] {
+        if !secrets.contains(&form) {
+            secrets.push(form);
+        }
+    }
+    // Longest first, so a secret containing another is masked as a whole.
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+}
+
+/// Mask the value of any `password=` query parameter or `password = "…"`
+/// TOML assignment, whether or not the password was registered.
+fn mask_password_param(text: &str) -> Cow<'_, str> {
+    const KEY: &str = "password";
+    if !text.contains(KEY) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find(KEY) {
+        let (head, tail) = rest.split_at(pos + KEY.len());
+        out.push_str(head);
+        let Some(after_eq) = tail.trim_start_matches([' ', '\t']).strip_prefix('=') else {
+            rest = tail;
+            continue;
+        };
+        let value = after_eq.trim_start_matches([' ', '\t']);
+        out.push_str(&tail[..tail.len() - value.len()]);
+        let end = match value.chars().next() {
+            // Quoted: up to the closing quote, or the end of the line if
+            // the string is unterminated.
+            Some(quote @ ('"' | '\'')) => {
+                let body = &value[1..];
+                out.push(quote);
+                let close = body.find([quote, '\n']).unwrap_or(body.len());
+                if close > 0 {
+                    out.push_str(MASK);
+                }
+                rest = &body[close..];
+                continue;
+            }
+            _ => value
+                .find(|c: char| c == '&' || c == '#' || c == '"' || c == ')' || c.is_whitespace())
+                .unwrap_or(value.len()),
+        };
+        if end > 0 {
+            out.push_str(MASK);
+        }
+        rest = &value[end..];
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Replace every registered secret in `text` with `***`.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut out = mask_password_param(text);
+    let secrets = SECRETS.read().unwrap_or_else(|e| e.into_inner());
+    for secret in secrets.iter() {
+        if out.contains(secret.as_str()) {
+            out = Cow::Owned(out.replace(secret.as_str(), MASK));
+        }
+    }
+    out
+}
+
+/// Wraps the real logger and redacts every message before it is formatted,
+/// so all `LOG_STYLE`s and levels are covered.
+struct RedactingLogger<L> {
+    inner: L,
+}
+
+impl<L: Log> Log for RedactingLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        self.inner.log(
+            &Record::builder()
+                .args(format_args!("{}", redact(&message)))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// Install `logger` as the global logger behind the redaction layer,
+/// letting through records up to `max_level`.
+pub fn install_logger(logger: impl Log + 'static, max_level: LevelFilter) {
+    log::set_boxed_logger(Box::new(RedactingLogger { inner: logger }))
+        .expect("logger already initialised");
+    log::set_max_level(max_level);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mask_password_param;
+
+    #[test]
+    fn masks_query_parameters() {
+        assert_eq!(
+            mask_password_param("GET /update?host=%40&password=abc123&ip=1.2.3.4"),
+            "GET /update?host=%40&password=***&ip=1.2.3.4"
+        );
+        assert_eq!(
+            mask_password_param("url (https://x/update?password=abc)"),
+            "url (https://x/update?password=***)"
+        );
+        assert_eq!(mask_password_param("password=&ip=1"), "password=&ip=1");
+    }
+
+    #[test]
+    fn masks_toml_assignments() {
+        assert_eq!(
+            mask_password_param(r#"password = "s3cret" # comment"#),
+            r#"password = "***" # comment"#
+        );
+        assert_eq!(mask_password_param("password='s3cret'"), "password='***'");
+        assert_eq!(
+            mask_password_param("2 | password = \"s3cret\n  |"),
+            "2 | password = \"***\n  |"
+        );
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        for text in [
+            "no secrets here",
+            "wrong password",
+            "password_file = \"/run/secrets/nc\"",
+            "Passwords do not match",
+        ] {
+            assert_eq!(mask_password_param(text), text);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use quick_xml::{events::Event, Reader};
+use std::net::IpAddr;
+
+use crate::error::UpdateError;
+
+/// One `<ErrN>` entry of a Namecheap response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NamecheapError {
+    /// The `N` in `<ErrN>`.
+    pub number: u32,
+    pub message: String,
+}
+
+/// One `<response>` entry of a Namecheap response.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResponseEntry {
+    /// `<ResponseNumber>`, e.g. 304156 for a password mismatch.
+    pub number: Option<u32>,
+    pub description: Option<String>,
+    pub response_string: Option<String>,
+}
+
+/// Namecheap's `<interface-response>` to a DDNS update.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NamecheapResponse {
+    pub command: Option<String>,
+    pub language: Option<String>,
+    /// The address Namecheap actually applied.
+    pub ip: Option<IpAddr>,
+    pub err_count: u32,
+    pub errors: Vec<NamecheapError>,
+    pub response_count: u32,
+    pub responses: Vec<ResponseEntry>,
+    pub done: bool,
+    pub debug: Option<String>,
+}
+
+impl NamecheapResponse {
+    pub fn is_success(&self) -> bool {
+        self.err_count == 0
+    }
+
+    /// All `<ResponseNumber>` codes.
+    pub fn codes(&self) -> Vec<u32> {
+        self.responses.iter().filter_map(|r| r.number).collect()
+    }
+
+    /// Every human readable message: errors first, then response
+    /// descriptions and strings.
+    pub fn messages(&self) -> Vec<&str> {
+        let errors = self.errors.iter().map(|e| e.message.as_str());
+        let descriptions = self
+            .responses
+            .iter()
+            .filter_map(|r| r.description.as_deref());
+        let strings = self
+            .responses
+            .iter()
+            .filter_map(|r| r.response_string.as_deref());
+        errors.chain(descriptions).chain(strings).collect()
+    }
+}
+
+/// Parse Namecheap's XML DDNS response.
+/// Returns the parsed response whether or not Namecheap reported errors
+/// (check `is_success`), or Err(UpdateError::Malformed) if the XML is
+/// malformed or is not an `<interface-response>` with an `<ErrCount>`
+/// (maintenance pages, proxy errors, empty bodies).
+pub fn parse_namecheap_response(xml: &str) -> Result<NamecheapResponse, UpdateError> {
+    let mut reader = Reader::from_str(xml);
+    // quick-xml 0.36: configure trimming via config_mut()
+    reader.config_mut().trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut current_tag: Option<String> = None;
+    let mut response = NamecheapResponse::default();
+    let mut root: Option<String> = None;
+    let mut err_count: Option<String> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let tag = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                if root.is_none() {
+                    root = Some(tag.clone());
+                }
+                if tag == "response" {
+                    response.responses.push(ResponseEntry::default());
+                }
+                current_tag = Some(tag);
+            }
+            Ok(Event::Text(e)) => {
+                if let Some(tag) = &current_tag {
+                    let text = e.unescape().unwrap_or_default().trim().to_string();
+                    if !text.is_empty() {
+                        if tag == "ErrCount" {
+                            err_count = Some(text.clone());
+                        }
+                        apply_field(&mut response, tag, text);
+                    }
+                }
+            }
+            Ok(Event::CData(e)) if current_tag.as_deref() == Some("debug") => {
+                let text = String::from_utf8_lossy(&e).trim().to_string();
+                if !text.is_empty() {
+                    response.debug = Some(text);
+                }
+            }
+            Ok(Event::End(_)) => {
+                current_tag = None;
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                return Err(UpdateError::Malformed(e.to_string()));
+            }
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    match root.as_deref() {
+        Some("interface-response") => {}
+        Some(other) => {
+            return Err(UpdateError::Malformed(format!(
+                "expected <interface-response>, got <{other}>"
+            )))
+        }
+        None => {
+            return Err(UpdateError::Malformed(
+                "no <interface-response> element".to_string(),
+            ))
+        }
+    }
+    match err_count.as_deref().map(str::parse::<u32>) {
+        Some(Ok(_)) => Ok(response),
+        Some(Err(_)) => Err(UpdateError::Malformed(format!(
+            "invalid <ErrCount> {:?}",
+            err_count.unwrap_or_default()
+        ))),
+        None => Err(UpdateError::Malformed("missing <ErrCount>".to_string())),
+    }
+}
+
+/// The `<response>` entry currently being filled in.
+fn current_entry(response: &mut NamecheapResponse) -> &mut ResponseEntry {
+    if response.responses.is_empty() {
+        response.responses.push(ResponseEntry::default());
+    }
+    response.responses.last_mut().unwrap()
+}
+
+fn apply_field(response: &mut NamecheapResponse, tag: &str, text: String) {
+    match tag {
+        "Command" => response.command = Some(text),
+        "Language" => response.language = Some(text),
+        "IP" => response.ip = text.parse().ok(),
+        "ErrCount" => response.err_count = text.parse().unwrap_or(0),
+        "ResponseCount" => response.response_count = text.parse().unwrap_or(0),
+        "Done" => response.done = text.eq_ignore_ascii_case("true"),
+        "debug" => response.debug = Some(text),
+        "ResponseNumber" => current_entry(response).number = text.parse().ok(),
+        "Description" => current_entry(response).description = Some(text),
+        "ResponseString" => current_entry(response).response_string = Some(text),
+        // <Err1>, <Err2>, ...
+        t if t.starts_with("Err") => {
+            let number = t["Err".len()..].parse().unwrap_or(0);
+            response.errors.push(NamecheapError {
+                number,
+                message: text,
+            });
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,235 @@
+//! Ask the home router for its WAN address: UPnP IGD `GetExternalIPAddress`
+//! or NAT-PMP external address requests (opcode 0). PCP-only routers answer
+//! NAT-PMP with UNSUPP_VERSION and are not supported.
+
+use quick_xml::{events::Event, Reader};
+use reqwest::{Client, Url};
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, Instant};
+
+use crate::detect::Family;
+use crate::dns::{connect_udp, exchange, invalid, resolve_server};
+
+const NATPMP_PORT: u16 = 5351;
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+const ROUTER_TIMEOUT: Duration = Duration::from_secs(3);
+/// Initial NAT-PMP retransmission interval, doubled after each try (RFC 6886).
+const NATPMP_RTO: Duration = Duration::from_millis(250);
+
+const WAN_SERVICES: [&str; 2] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:",
+    "urn:schemas-upnp-org:service:WANPPPConnection:",
+];
+
+fn timed_out(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("{what} timed out"))
+}
+
+/// Routers only report their IPv4 WAN address.
+fn ipv4_only(family: Family, what: &str) -> io::Result<()> {
+    match family {
+        Family::V4 => Ok(()),
+        Family::V6 => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{what} only reports an IPv4 address"),
+        )),
+    }
+}
+
+/// IPv4 default gateway from the kernel routing table (Linux only).
+fn default_gateway() -> io::Result<Ipv4Addr> {
+    let routes = fs::read_to_string("/proc/net/route")?;
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(1);
+            let destination = fields.next()?;
+            let gateway = fields.next()?;
+            (destination == "00000000").then_some(gateway)
+        })
+        .filter_map(|gw| u32::from_str_radix(gw, 16).ok())
+        .find(|&gw| gw != 0)
+        // The table holds addresses in host (little-endian) byte order.
+        .map(|gw| Ipv4Addr::from(gw.swap_bytes()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no default gateway found"))
+}
+
+/// NAT-PMP external address request to `gateway` (`host[:port]`, or the
+/// default gateway when `None`).
+pub(crate) async fn query_natpmp(gateway: Option<&str>, family: Family) -> io::Result<String> {
+    ipv4_only(family, "NAT-PMP")?;
+    let server = match gateway {
+        Some(spec) => resolve_server(spec, NATPMP_PORT, Family::V4).await?,
+        None => SocketAddr::from((default_gateway()?, NATPMP_PORT)),
+    };
+
+    let socket = connect_udp(server).await?;
+    exchange(
+        &socket,
+        &[0, 0],
+        NATPMP_RTO,
+        ROUTER_TIMEOUT,
+        "NAT-PMP request",
+        |reply| Some(parse_natpmp(reply)),
+    )
+    .await
+}
+
+fn parse_natpmp(reply: &[u8]) -> io::Result<String> {
+    if reply.len() < 12 || reply[0] != 0 || reply[1] != 128 {
+        return Err(invalid("unexpected NAT-PMP response"));
+    }
+    match u16::from_be_bytes([reply[2], reply[3]]) {
+        0 => Ok(Ipv4Addr::new(reply[8], reply[9], reply[10], reply[11]).to_string()),
+        code => Err(invalid(format!(
+            "NAT-PMP request failed with result code {code}"
+        ))),
+    }
+}
+
+/// Find an Internet Gateway Device via SSDP and return its description URL.
+async fn discover_igd() -> io::Result<String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {SSDP_ADDR}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\n\
+         ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n"
+    );
+    socket.send_to(search.as_bytes(), SSDP_ADDR).await?;
+
+    let deadline = Instant::now() + ROUTER_TIMEOUT;
+    let mut buf = [0u8; 2048];
+    loop {
+        let (n, _) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf))
+            .await
+            .map_err(|_| timed_out("UPnP discovery"))??;
+        let reply = String::from_utf8_lossy(&buf[..n]);
+        let location = reply.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("location")
+                .then(|| value.trim().to_string())
+        });
+        if let Some(location) = location {
+            return Ok(location);
+        }
+    }
+}
+
+/// Control URL of the WAN connection service in a device description.
+fn wan_control_url(description: &str) -> io::Result<(String, String)> {
+    let mut reader = Reader::from_str(description);
+    reader.config_mut().trim_text(true);
+
+    let mut current_tag = String::new();
+    let mut service_type = String::new();
+    let mut control_url = String::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                current_tag = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                if current_tag == "service" {
+                    service_type.clear();
+                    control_url.clear();
+                }
+            }
+            Ok(Event::Text(e)) => {
+                let text = e.unescape().unwrap_or_default().trim().to_string();
+                match current_tag.as_str() {
+                    "serviceType" => service_type = text,
+                    "controlURL" => control_url = text,
+                    _ => {}
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.local_name().as_ref() == b"service"
+                    && WAN_SERVICES.iter().any(|s| service_type.starts_with(s))
+                    && !control_url.is_empty()
+                {
+                    return Ok((service_type, control_url));
+                }
+                current_tag.clear();
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(invalid(format!("invalid UPnP description: {e}"))),
+            _ => {}
+        }
+    }
+    Err(invalid("router exposes no WANIPConnection service"))
+}
+
+/// Text of the first `tag` element (namespace prefix ignored).
+fn element_text(xml: &str, tag: &str) -> Option<String> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut inside = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => inside = e.local_name().as_ref() == tag.as_bytes(),
+            Ok(Event::Text(e)) if inside => {
+                return Some(e.unescape().ok()?.trim().to_string());
+            }
+            Ok(Event::End(_)) => inside = false,
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+/// UPnP IGD `GetExternalIPAddress`. `description` is the router's device
+/// description URL; without it the router is discovered via SSDP.
+pub(crate) async fn query_upnp(
+    client: &Client,
+    description: Option<&str>,
+    family: Family,
+) -> io::Result<String> {
+    ipv4_only(family, "UPnP")?;
+    let location = match description {
+        Some(url) => url.to_string(),
+        None => discover_igd().await?,
+    };
+    let base = Url::parse(&location).map_err(|e| invalid(format!("{location}: {e}")))?;
+
+    let fetch = async {
+        let description = client
+            .get(base.clone())
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let (service, control) = wan_control_url(&description)?;
+        let control = base
+            .join(&control)
+            .map_err(|e| invalid(format!("{control}: {e}")))?;
+
+        let body = format!(
+            "<?xml version=\"1.0\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+             <s:Body><u:GetExternalIPAddress xmlns:u=\"{service}\"/></s:Body></s:Envelope>"
+        );
+        let response = client
+            .post(control)
+            .header("Content-Type", "text/xml; charset=\"utf-8\"")
+            .header("SOAPAction", format!("\"{service}#GetExternalIPAddress\""))
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(response)
+    };
+
+    let response = timeout(ROUTER_TIMEOUT, fetch)
+        .await
+        .map_err(|_| timed_out("UPnP request"))?
+        .map_err(io::Error::other)?;
+    element_text(&response, "NewExternalIPAddress")
+        .filter(|ip| !ip.is_empty())
+        .ok_or_else(|| invalid("router did not report an external IP address"))
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::detect::Family;
+use crate::error::UpdateError;
+use crate::persist;
+use crate::redact::redact;
+
+/// What we know about a single `host.domain` A or AAAA record.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct HostState {
+    /// Last IP Namecheap accepted for this host.
+    #[serde(default)]
+    pub last_ip: Option<String>,
+    /// Unix timestamp (seconds) of the last successful update.
+    #[serde(default)]
+    pub last_success: Option<u64>,
+    /// Message of the most recent failure, cleared on success.
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// IP of the last failed attempt.
+    #[serde(default)]
+    pub failed_ip: Option<String>,
+    /// Unix timestamp (seconds) before which the failed IP is not retried.
+    #[serde(default)]
+    pub retry_after: Option<u64>,
+    /// Whether Namecheap rejected the failed update, as opposed to it not
+    /// getting through, so a held host is reported the same way as the
+    /// failure that put it on hold.
+    #[serde(default)]
+    pub rejected: bool,
+}
+
+/// Persistent per-host update state, stored as JSON in the data volume.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    hosts: BTreeMap<String, HostState>,
+}
+
+/// A records keep the original `domain/host` key so existing state files
+/// stay valid; AAAA records get their own entry.
+fn key(domain: &str, host: &str, family: Family) -> String {
+    match family {
+        Family::V4 => format!("{domain}/{host}"),
+        Family::V6 => format!("{domain}/{host}/AAAA"),
+    }
+}
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl State {
+    /// Load the state file. A missing file yields an empty state; an
+    /// unreadable or corrupt one is logged and treated as empty so every
+    /// host simply gets updated again.
+    pub fn load(path: &Path) -> Self {
+        persist::load(path, "state file")
+    }
+
+    /// Write the state file atomically.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        persist::save(self, path)
+    }
+
+    pub fn get(&self, domain: &str, host: &str, family: Family) -> Option<&HostState> {
+        self.hosts.get(&key(domain, host, family))
+    }
+
+    /// A host needs an update if it has never been pushed `ip`, or if its
+    /// last attempt failed and its retry delay for that IP has passed.
+    pub fn needs_update(&self, domain: &str, host: &str, family: Family, ip: &str) -> bool {
+        if self.is_held(domain, host, family, ip) {
+            return false;
+        }
+
+        match self.get(domain, host, family) {
+            Some(h) => h.last_ip.as_deref() != Some(ip) || h.consecutive_failures > 0,
+            None => true,
+        }
+    }
+
+    /// Whether pushing `ip` to this host recently failed and its retry delay
+    /// has not yet passed.
+    pub fn is_held(&self, domain: &str, host: &str, family: Family, ip: &str) -> bool {
+        self.get(domain, host, family).is_some_and(|h| {
+            h.failed_ip.as_deref() == Some(ip) && h.retry_after.is_some_and(|at| now_secs() < at)
+        })
+    }
+
+    /// Forget every retry delay, e.g. on startup after the configuration
+    /// may have been fixed.
+    pub fn clear_retry_delays(&mut self) {
+        for h in self.hosts.values_mut() {
+            h.retry_after = None;
+        }
+    }
+
+    pub fn record_success(&mut self, domain: &str, host: &str, family: Family, ip: &str) {
+        let entry = self.hosts.entry(key(domain, host, family)).or_default();
+        entry.last_ip = Some(ip.to_string());
+        entry.last_success = Some(now_secs());
+        entry.last_error = None;
+        entry.consecutive_failures = 0;
+        entry.failed_ip = None;
+        entry.retry_after = None;
+        entry.rejected = false;
+    }
+
+    /// Record a failed attempt to push `ip`; with `retry_in`, the same IP is
+    /// not retried before that delay has passed.
+    pub fn record_failure(
+        &mut self,
+        domain: &str,
+        host: &str,
+        family: Family,
+        ip: &str,
+        error: &UpdateError,
+        retry_in: Option<Duration>,
+    ) {
+        let entry = self.hosts.entry(key(domain, host, family)).or_default();
+        entry.last_error = Some(redact(&error.to_string()).into_owned());
+        entry.rejected = matches!(error, UpdateError::Namecheap(_));
+        entry.consecutive_failures = entry.consecutive_failures.saturating_add(1);
+        entry.failed_ip = Some(ip.to_string());
+        entry.retry_after = retry_in.map(|d| now_secs().saturating_add(d.as_secs()));
+    }
+}
@@ -0,0 +1,120 @@
+//! STUN Binding Request (RFC 5389) over UDP: the server reports the address
+//! and port our request arrived from.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use crate::backoff::random_up_to;
+use crate::detect::Family;
+use crate::dns::{connect_udp, exchange, invalid, resolve_server};
+
+const STUN_PORT: u16 = 3478;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Initial retransmission timeout, doubled after each try (RFC 5389 §7.2.1).
+const STUN_RTO: Duration = Duration::from_millis(500);
+
+const MAGIC_COOKIE: u32 = 0x2112_a442;
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+fn transaction_id() -> [u8; 12] {
+    let mut id = [0u8; 12];
+    id[..8].copy_from_slice(&random_up_to(u64::MAX - 1).to_be_bytes());
+    id[8..].copy_from_slice(&(random_up_to(u32::MAX as u64) as u32).to_be_bytes());
+    id
+}
+
+/// Decode a (XOR-)MAPPED-ADDRESS value; `xor` holds the cookie followed by
+/// the transaction ID, or is `None` for the plain attribute.
+fn decode_address(value: &[u8], xor: Option<&[u8; 16]>) -> Option<IpAddr> {
+    let family = *value.get(1)?;
+    let unmask = |bytes: &[u8]| -> Vec<u8> {
+        match xor {
+            Some(key) => bytes.iter().zip(key.iter()).map(|(b, k)| b ^ k).collect(),
+            None => bytes.to_vec(),
+        }
+    };
+    match family {
+        0x01 => {
+            let b = unmask(value.get(4..8)?);
+            Some(IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3])))
+        }
+        0x02 => {
+            let b: [u8; 16] = unmask(value.get(4..20)?).try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(b)))
+        }
+        _ => None,
+    }
+}
+
+fn parse_response(msg: &[u8], id: &[u8; 12]) -> io::Result<IpAddr> {
+    if msg.len() < 20 {
+        return Err(invalid("truncated STUN message"));
+    }
+    if u16::from_be_bytes([msg[0], msg[1]]) != BINDING_SUCCESS {
+        return Err(invalid(
+            "STUN server did not return a Binding success response",
+        ));
+    }
+
+    let mut key = [0u8; 16];
+    key[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    key[4..].copy_from_slice(id);
+
+    let len = u16::from_be_bytes([msg[2], msg[3]]) as usize;
+    let attrs = msg
+        .get(20..20 + len)
+        .ok_or_else(|| invalid("truncated STUN message"))?;
+
+    let mut mapped = None;
+    let mut pos = 0;
+    while pos + 4 <= attrs.len() {
+        let kind = u16::from_be_bytes([attrs[pos], attrs[pos + 1]]);
+        let vlen = u16::from_be_bytes([attrs[pos + 2], attrs[pos + 3]]) as usize;
+        let value = attrs
+            .get(pos + 4..pos + 4 + vlen)
+            .ok_or_else(|| invalid("truncated STUN attribute"))?;
+        match kind {
+            ATTR_XOR_MAPPED_ADDRESS => {
+                if let Some(ip) = decode_address(value, Some(&key)) {
+                    return Ok(ip);
+                }
+            }
+            ATTR_MAPPED_ADDRESS => mapped = mapped.or(decode_address(value, None)),
+            _ => {}
+        }
+        // Attributes are padded to a multiple of four bytes.
+        pos += 4 + vlen.div_ceil(4) * 4;
+    }
+
+    mapped.ok_or_else(|| invalid("STUN response has no mapped address"))
+}
+
+/// Send a Binding Request to `server` (`host[:port]`, port 3478 by default)
+/// over `family` and return the address the server saw us coming from.
+pub(crate) async fn query_stun(server: &str, family: Family) -> io::Result<String> {
+    let server = resolve_server(server, STUN_PORT, family).await?;
+    let id = transaction_id();
+
+    let mut request = Vec::with_capacity(20);
+    request.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+    request.extend_from_slice(&0u16.to_be_bytes());
+    request.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request.extend_from_slice(&id);
+
+    let socket = connect_udp(server).await?;
+    exchange(
+        &socket,
+        &request,
+        STUN_RTO,
+        QUERY_TIMEOUT,
+        "STUN request",
+        // Ignore datagrams that belong to another transaction.
+        |reply| (reply.get(8..20) == Some(&id[..])).then(|| parse_response(reply, &id)),
+    )
+    .await
+    .map(|ip| ip.to_string())
+}
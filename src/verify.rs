@@ -0,0 +1,152 @@
+//! Look up what Namecheap's authoritative nameservers actually publish for
+//! a host, instead of trusting the local state file.
+
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::time::{sleep, timeout_at, Instant};
+
+use crate::detect::Family;
+use crate::dns::{query, resolve_server, Answer, RecordType, DNS_PORT};
+
+/// Pause between propagation checks.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Nameservers of domains using Namecheap's BasicDNS/FreeDNS.
+pub const DEFAULT_DNS_SERVERS: &str = "dns1.registrar-servers.com,dns2.registrar-servers.com";
+
+/// The name a host record publishes: `@` is the domain itself.
+pub fn fqdn(host: &str, domain: &str) -> String {
+    if host == "@" {
+        domain.to_string()
+    } else {
+        format!("{host}.{domain}")
+    }
+}
+
+/// A (or AAAA) addresses published for `name`, asking each of `servers`
+/// (`host[:port]`) in turn until one answers. An empty list means the
+/// record does not exist.
+pub async fn published_addresses(
+    name: &str,
+    family: Family,
+    servers: &[String],
+) -> io::Result<Vec<IpAddr>> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no nameservers configured");
+
+    for server in servers {
+        let addr = match resolve_server(server, DNS_PORT, Family::V4).await {
+            Ok(addr) => addr,
+            Err(_) => match resolve_server(server, DNS_PORT, Family::V6).await {
+                Ok(addr) => addr,
+                Err(e) => {
+                    last_error = e;
+                    continue;
+                }
+            },
+        };
+
+        match query(addr, name, RecordType::address(family)).await {
+            Ok(answers) => {
+                return Ok(answers
+                    .into_iter()
+                    .filter_map(|a| match a {
+                        Answer::Ip(ip) if family.matches(&ip) => Some(ip),
+                        _ => None,
+                    })
+                    .collect());
+            }
+            Err(e) => last_error = io::Error::new(e.kind(), format!("{server}: {e}")),
+        }
+    }
+
+    Err(last_error)
+}
+
+/// How one updated record fared in `wait_for_propagation`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Propagation {
+    pub name: String,
+    /// Time until every answering nameserver published the new address;
+    /// `None` if the deadline passed first.
+    pub verified_after: Option<Duration>,
+    /// Addresses seen in the last lookup, for reporting unverified records.
+    pub last_seen: Vec<IpAddr>,
+}
+
+/// Poll `servers` until each of `names` publishes `ip` on every nameserver
+/// that answers, or `timeout` passes. Lookups still running at the deadline
+/// are abandoned and their names reported as unverified.
+pub async fn wait_for_propagation(
+    names: &[String],
+    family: Family,
+    ip: &str,
+    servers: &[String],
+    timeout: Duration,
+) -> Vec<Propagation> {
+    let started = Instant::now();
+    let deadline = started + timeout;
+    let mut pending: Vec<Propagation> = names
+        .iter()
+        .map(|name| Propagation {
+            name: name.clone(),
+            verified_after: None,
+            last_seen: Vec::new(),
+        })
+        .collect();
+    let mut done = Vec::new();
+
+    loop {
+        let mut still_pending = Vec::new();
+        let mut expired = false;
+        for mut record in pending {
+            if expired {
+                still_pending.push(record);
+                continue;
+            }
+            let mut answered = false;
+            let mut everywhere = true;
+            let mut seen: Vec<IpAddr> = Vec::new();
+            for server in servers {
+                let lookup =
+                    published_addresses(&record.name, family, std::slice::from_ref(server));
+                let Ok(result) = timeout_at(deadline, lookup).await else {
+                    expired = true;
+                    break;
+                };
+                let Ok(published) = result else {
+                    continue;
+                };
+                answered = true;
+                everywhere &= published.iter().any(|p| p.to_string() == ip);
+                for p in published {
+                    if !seen.contains(&p) {
+                        seen.push(p);
+                    }
+                }
+            }
+            // A pass cut short by the deadline keeps the previous answers.
+            if !expired {
+                record.last_seen = seen;
+            }
+
+            if answered && everywhere && !expired {
+                record.verified_after = Some(started.elapsed());
+                done.push(record);
+            } else {
+                still_pending.push(record);
+            }
+        }
+        pending = still_pending;
+
+        let now = Instant::now();
+        if pending.is_empty() || expired || now >= deadline {
+            break;
+        }
+        sleep(POLL_INTERVAL.min(deadline - now)).await;
+    }
+
+    done.extend(pending);
+    done
+}
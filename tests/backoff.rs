@@ -0,0 +1,18 @@
+use namecheap_ddns::Backoff;
+use std::time::Duration;
+
+#[test]
+fn delay_grows_exponentially_with_jitter_and_cap() {
+    let backoff = Backoff::new(10, Duration::from_millis(100), Duration::from_millis(1000));
+
+    for _ in 0..50 {
+        let first = backoff.delay(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+
+        let third = backoff.delay(3);
+        assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+
+        let capped = backoff.delay(40);
+        assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_millis(1000));
+    }
+}
@@ -0,0 +1,125 @@
+use namecheap_ddns::{Backoff, Config, DomainConfig, Family};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn write_config(contents: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let path = std::env::temp_dir().join(format!(
+        "namecheap-ddns-config-{}-{}.toml",
+        std::process::id(),
+        n
+    ));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn problems(contents: &str) -> Vec<String> {
+    match Config::from_file(&write_config(contents)) {
+        Ok(_) => panic!("config was accepted:\n{contents}"),
+        Err(e) => e.problems,
+    }
+}
+
+#[test]
+fn parses_several_domains_with_their_own_intervals() {
+    let config = Config::from_file(&write_config(
+        r#"
+interval_seconds = 120
+
+[[domain]]
+name = "example.com"
+password = "first"
+hosts = ["@", " www "]
+
+[[domain]]
+name = "example.org"
+password = "second"
+hosts = ["home"]
+interval_seconds = 600
+"#,
+    ))
+    .unwrap();
+
+    assert_eq!(config.domains.len(), 2);
+    assert_eq!(config.domains[0].domain, "example.com");
+    assert_eq!(config.domains[0].password, "first");
+    assert_eq!(config.domains[0].hosts, vec!["@", "www"]);
+    assert_eq!(config.domains[0].interval_secs, 120);
+    assert_eq!(config.domains[1].domain, "example.org");
+    assert_eq!(config.domains[1].hosts, vec!["home"]);
+    assert_eq!(config.domains[1].interval_secs, 600);
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+    let top = problems("intervall_seconds = 60\n");
+    assert_eq!(top.len(), 1);
+    assert!(
+        top[0].contains("unknown field `intervall_seconds`"),
+        "{top:?}"
+    );
+    assert!(top[0].contains("line 1"), "{top:?}");
+
+    let nested = problems(
+        r#"
+[[domain]]
+name = "example.com"
+password = "secret"
+host = ["@"]
+"#,
+    );
+    assert!(nested[0].contains("unknown field `host`"), "{nested:?}");
+    assert!(nested[0].contains("line 5"), "{nested:?}");
+}
+
+#[test]
+fn an_empty_domain_list_is_rejected() {
+    let problems = problems("interval_seconds = 60\n");
+    assert_eq!(problems, vec!["config file defines no [[domain]] entries"]);
+}
+
+#[test]
+fn password_file_is_trimmed_and_conflicts_with_password() {
+    let secret = write_config("from-file\r\n");
+    let config = Config::from_file(&write_config(&format!(
+        "[[domain]]\nname = \"example.com\"\npassword_file = \"{}\"\nhosts = [\"@\"]\n",
+        secret.display()
+    )))
+    .unwrap();
+    assert_eq!(config.domains[0].password, "from-file");
+
+    let both = problems(&format!(
+        "[[domain]]\nname = \"example.com\"\npassword = \"inline\"\n\
+         password_file = \"{}\"\nhosts = [\"@\"]\n",
+        secret.display()
+    ));
+    assert_eq!(
+        both,
+        vec!["domain #1: both password and password_file are set, use only one"]
+    );
+
+    let unreadable = problems(
+        "[[domain]]\nname = \"example.com\"\n\
+         password_file = \"/nonexistent/namecheap-ddns/password\"\nhosts = [\"@\"]\n",
+    );
+    assert_eq!(unreadable.len(), 1);
+    assert!(
+        unreadable[0].starts_with("domain #1: failed to read password_file"),
+        "{unreadable:?}"
+    );
+}
+
+#[test]
+fn configs_built_in_code_start_from_the_defaults() {
+    let domain = DomainConfig::new("example.com", "secret", vec!["@".to_string()])
+        .with_aaaa_hosts(vec!["@".to_string()])
+        .with_interval_secs(60);
+    let config = Config::new(vec![domain]);
+
+    assert!(config.validate().is_ok());
+    assert!(config.wants_ipv6());
+    assert_eq!(config.domains[0].interval_secs, 60);
+    assert_eq!(config.detect6.family, Family::V6);
+    assert_eq!(config.retry, Backoff::default());
+}
@@ -0,0 +1,56 @@
+use namecheap_ddns::{is_public_ipv4, is_public_ipv6};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+#[test]
+fn rejects_non_public_ranges() {
+    let rejected = [
+        "10.0.0.1",
+        "172.16.5.4",
+        "192.168.1.1",
+        "100.64.0.1",
+        "100.127.255.254",
+        "127.0.0.1",
+        "169.254.1.1",
+        "224.0.0.1",
+        "255.255.255.255",
+        "0.0.0.0",
+        "192.0.0.8",
+        "192.0.2.1",
+        "198.51.100.1",
+        "203.0.113.1",
+        "198.18.0.1",
+        "240.0.0.1",
+    ];
+    for ip in rejected {
+        assert!(!is_public_ipv4(ip.parse::<Ipv4Addr>().unwrap()), "{ip}");
+    }
+}
+
+#[test]
+fn accepts_public_addresses() {
+    for ip in ["1.1.1.1", "93.184.216.34", "100.128.0.1", "172.32.0.1"] {
+        assert!(is_public_ipv4(ip.parse::<Ipv4Addr>().unwrap()), "{ip}");
+    }
+}
+
+#[test]
+fn only_global_unicast_ipv6_is_public() {
+    for ip in [
+        "::1",
+        "fe80::1",
+        "fd00::1",
+        "ff02::1",
+        "2001:db8::1",
+        "2001:2::1",
+        "::",
+    ] {
+        assert!(!is_public_ipv6(ip.parse::<Ipv6Addr>().unwrap()), "{ip}");
+    }
+    for ip in [
+        "2606:4700:4700::1111",
+        "2a00:1450:4001::200e",
+        "2001:4860::8888",
+    ] {
+        assert!(is_public_ipv6(ip.parse::<Ipv6Addr>().unwrap()), "{ip}");
+    }
+}
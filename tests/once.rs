@@ -0,0 +1,1187 @@
+//! End-to-end tests that run the binary in `--once` mode against a local
+//! stand-in for both the IP provider and the Namecheap DDNS endpoint.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PASSWORD: &str = "0123456789abcdef0123456789abcdef";
+
+const SUCCESS_XML: &str = r#"<?xml version="1.0" encoding="utf-16"?>
+<interface-response>
+  <Command>SETDNSHOST</Command>
+  <Language>eng</Language>
+  <IP>93.184.216.34</IP>
+  <ErrCount>0</ErrCount>
+  <ResponseCount>0</ResponseCount>
+  <Done>true</Done>
+  <debug><![CDATA[]]></debug>
+</interface-response>"#;
+
+const BAD_PASSWORD_XML: &str = r#"<?xml version="1.0" encoding="utf-16"?>
+<interface-response>
+  <Command>SETDNSHOST</Command>
+  <Language>eng</Language>
+  <ErrCount>1</ErrCount>
+  <errors>
+    <Err1>Passwords do not match</Err1>
+  </errors>
+  <ResponseCount>1</ResponseCount>
+  <responses>
+    <response>
+      <ResponseNumber>304156</ResponseNumber>
+      <ResponseString>Validation error; invalid ; password</ResponseString>
+    </response>
+  </responses>
+  <Done>true</Done>
+  <debug><![CDATA[]]></debug>
+</interface-response>"#;
+
+/// Canned reply for one path on the mock server.
+#[derive(Clone)]
+struct Reply {
+    status: u16,
+    body: &'static str,
+    delay: Duration,
+}
+
+/// Minimal HTTP/1.1 server: answers each route with a canned body
+/// (`/update` and any `/ip*` path) and records every request target it sees.
+struct MockServer {
+    base: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    fn start(ip: Reply, update: Reply) -> Self {
+        MockServer::with_routes(vec![("/ip", ip), ("/update", update)])
+    }
+
+    fn with_routes(routes: Vec<(&'static str, Reply)>) -> Self {
+        MockServer::bind("127.0.0.1:0", routes)
+    }
+
+    fn bind(addr: &str, routes: Vec<(&'static str, Reply)>) -> Self {
+        let listener = TcpListener::bind(addr).unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        let routes = Arc::new(routes);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                let seen = Arc::clone(&seen);
+                let routes = Arc::clone(&routes);
+                thread::spawn(move || serve(stream, &seen, &routes));
+            }
+        });
+
+        MockServer { base, requests }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base, path)
+    }
+
+    fn update_requests(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.starts_with("/update"))
+            .cloned()
+            .collect()
+    }
+}
+
+fn serve(mut stream: TcpStream, seen: &Mutex<Vec<String>>, routes: &[(&str, Reply)]) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    // Drain headers and any body (UPnP SOAP requests are POSTs).
+    let mut line = String::new();
+    let mut body_len = 0;
+    while reader.read_line(&mut line).map(|n| n > 2).unwrap_or(false) {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                body_len = value.trim().parse().unwrap_or(0);
+            }
+        }
+        line.clear();
+    }
+    let mut body = vec![0; body_len];
+    let _ = reader.read_exact(&mut body);
+
+    let target = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+    seen.lock().unwrap().push(target.clone());
+
+    let path = target.split('?').next().unwrap_or_default();
+    let reply = routes
+        .iter()
+        .find(|(route, _)| *route == path)
+        .map(|(_, reply)| reply.clone())
+        .unwrap_or(Reply {
+            status: 404,
+            body: "",
+            delay: Duration::ZERO,
+        });
+
+    thread::sleep(reply.delay);
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.status,
+        reply.body.len(),
+        reply.body
+    );
+}
+
+/// UDP nameserver that answers every A question with `ip` and every TXT
+/// question with `ip` as text, like `myip.opendns.com` and Google's
+/// `o-o.myaddr.l.google.com` do for the querying address. The first `lost`
+/// queries go unanswered.
+fn start_dns_responder(ip: [u8; 4], lost: usize) -> String {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap().to_string();
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let mut seen = 0;
+        while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+            seen += 1;
+            if seen <= lost {
+                continue;
+            }
+            let query = &buf[..n];
+            let mut end = 12;
+            while query[end] != 0 {
+                end += 1 + query[end] as usize;
+            }
+            let question = &query[12..end + 5];
+            let qtype = u16::from_be_bytes([query[end + 1], query[end + 2]]);
+
+            let text = ip.map(|b| b.to_string()).join(".");
+            let rdata = match qtype {
+                1 => ip.to_vec(),
+                16 => [&[text.len() as u8], text.as_bytes()].concat(),
+                _ => Vec::new(),
+            };
+            let answers = u16::from(!rdata.is_empty());
+
+            let mut reply = vec![query[0], query[1], 0x81, 0x80, 0, 1];
+            reply.extend_from_slice(&answers.to_be_bytes());
+            reply.extend_from_slice(&[0, 0, 0, 0]);
+            reply.extend_from_slice(question);
+            if answers > 0 {
+                reply.extend_from_slice(&[0xc0, 0x0c]);
+                reply.extend_from_slice(&qtype.to_be_bytes());
+                reply.extend_from_slice(&[0, 1, 0, 0, 0, 60]);
+                reply.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+                reply.extend_from_slice(&rdata);
+            }
+            let _ = socket.send_to(&reply, peer);
+        }
+    });
+
+    addr
+}
+
+/// UDP STUN server answering every Binding Request with `ip` as the
+/// XOR-MAPPED-ADDRESS, after a SOFTWARE attribute that needs padding. The
+/// first `lost` requests go unanswered, and every reply is preceded by a
+/// truncated copy that must be ignored.
+fn start_stun_responder(ip: [u8; 4], lost: usize) -> String {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap().to_string();
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let mut seen = 0;
+        while let Ok((n, peer)) = socket.recv_from(&mut buf) {
+            if n < 20 || buf[..2] != [0x00, 0x01] {
+                continue;
+            }
+            seen += 1;
+            if seen <= lost {
+                continue;
+            }
+            let cookie = &buf[4..8];
+            let mut attrs = vec![0x80, 0x22, 0, 5];
+            attrs.extend_from_slice(b"mock\0\0\0\0");
+            attrs.extend_from_slice(&[0x00, 0x20, 0, 8, 0, 0x01]);
+            attrs.extend_from_slice(&(40000u16 ^ 0x2112).to_be_bytes());
+            attrs.extend(ip.iter().zip(cookie).map(|(b, k)| b ^ k));
+
+            let mut reply = vec![0x01, 0x01];
+            reply.extend_from_slice(&(attrs.len() as u16).to_be_bytes());
+            reply.extend_from_slice(&buf[4..20]);
+            reply.extend_from_slice(&attrs);
+            let _ = socket.send_to(&reply[..12], peer);
+            let _ = socket.send_to(&reply, peer);
+        }
+    });
+
+    addr
+}
+
+/// UDP NAT-PMP gateway answering external address requests with `ip`,
+/// after dropping the first `lost` requests as a lossy link would.
+fn start_natpmp_responder(ip: [u8; 4], lost: usize) -> String {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap().to_string();
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 16];
+        let mut seen = 0;
+        while let Ok((_, peer)) = socket.recv_from(&mut buf) {
+            seen += 1;
+            if seen <= lost {
+                continue;
+            }
+            let mut reply = vec![0, 128, 0, 0, 0, 0, 0x12, 0x34];
+            reply.extend_from_slice(&ip);
+            let _ = socket.send_to(&reply, peer);
+        }
+    });
+
+    addr
+}
+
+fn ok(body: &'static str) -> Reply {
+    Reply {
+        status: 200,
+        body,
+        delay: Duration::ZERO,
+    }
+}
+
+fn temp_path(extension: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let path = std::env::temp_dir().join(format!(
+        "namecheap-ddns-test-{}-{}.{extension}",
+        std::process::id(),
+        n
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn temp_state_path() -> PathBuf {
+    temp_path("json")
+}
+
+/// Write `contents` to a fresh temp file and return its path.
+fn temp_file(extension: &str, contents: &str) -> PathBuf {
+    let path = temp_path(extension);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// `--once` invocation against `server`, ready for extra env vars.
+fn command(server: &MockServer, state: &PathBuf, hosts: &str) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_namecheap-ddns"));
+    command
+        .arg("--once")
+        .env_clear()
+        .env("RUST_LOG", "trace")
+        .env("NC_DOMAIN", "example.com")
+        .env("NC_PASSWORD", PASSWORD)
+        .env("NC_HOSTS", hosts)
+        .env("NC_IP_PROVIDERS", server.url("/ip"))
+        .env("NC_ENDPOINT", server.url("/update"))
+        .env("NC_STATE_FILE", state)
+        .env("NC_RETRY_BASE_MS", "10")
+        .env("NC_RETRY_MAX_MS", "50");
+    command
+}
+
+fn run_once(server: &MockServer, state: &PathBuf, hosts: &str) -> Output {
+    command(server, state, hosts).output().unwrap()
+}
+
+fn logs(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn successful_update_exits_zero_and_records_state() {
+    let server = MockServer::start(ok("93.184.216.34\n"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+
+    let output = run_once(&server, &state, "@,www");
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 2);
+    assert!(updates[0].contains("host=%40"));
+    assert!(updates[0].contains("domain=example.com"));
+    assert!(updates[0].contains("ip=93.184.216.34"));
+
+    let saved = std::fs::read_to_string(&state).unwrap();
+    assert!(saved.contains("example.com/@"));
+    assert!(saved.contains("example.com/www"));
+    assert!(saved.contains("\"last_ip\": \"93.184.216.34\""));
+}
+
+#[test]
+fn unchanged_ip_does_not_call_namecheap_again() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+
+    assert_eq!(run_once(&server, &state, "@").status.code(), Some(0));
+    assert_eq!(run_once(&server, &state, "@").status.code(), Some(0));
+
+    assert_eq!(server.update_requests().len(), 1);
+}
+
+#[test]
+fn rejected_update_exits_four_and_is_held_back() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(BAD_PASSWORD_XML));
+    let state = temp_state_path();
+
+    let output = run_once(&server, &state, "@");
+    assert_eq!(output.status.code(), Some(4), "{}", logs(&output));
+    assert!(logs(&output).contains("Passwords do not match"));
+    assert!(logs(&output).contains("[auth]"));
+
+    let saved = std::fs::read_to_string(&state).unwrap();
+    assert!(saved.contains("\"consecutive_failures\": 1"));
+
+    // A bad password won't fix itself, so the host is not hammered but is
+    // still reported as failing.
+    let output = run_once(&server, &state, "@");
+    assert_eq!(output.status.code(), Some(4), "{}", logs(&output));
+    assert_eq!(server.update_requests().len(), 1);
+}
+
+#[test]
+fn http_error_exits_five() {
+    let server = MockServer::start(
+        ok("93.184.216.34"),
+        Reply {
+            status: 503,
+            body: "down for maintenance",
+            delay: Duration::ZERO,
+        },
+    );
+    let state = temp_state_path();
+
+    let output = run_once(&server, &state, "@");
+    assert_eq!(output.status.code(), Some(5), "{}", logs(&output));
+
+    // Server errors are transient: retried with backoff within the run...
+    assert_eq!(server.update_requests().len(), 3);
+    assert!(logs(&output).contains("Transient failure"));
+
+    // ...and a failed host must not count as up to date on the next run.
+    assert_eq!(run_once(&server, &state, "@").status.code(), Some(5));
+    assert_eq!(server.update_requests().len(), 6);
+}
+
+#[test]
+fn non_namecheap_bodies_are_retried_not_treated_as_success() {
+    for body in [
+        "",
+        "down for maintenance",
+        "<html><body>Proxy error</body></html>",
+    ] {
+        let server = MockServer::start(ok("93.184.216.34"), ok(body));
+        let state = temp_state_path();
+
+        let output = run_once(&server, &state, "@");
+        assert_eq!(output.status.code(), Some(5), "{body:?}: {}", logs(&output));
+        assert_eq!(server.update_requests().len(), 3, "{body:?}");
+
+        let saved = std::fs::read_to_string(&state).unwrap();
+        assert!(saved.contains("\"last_ip\": null"), "{body:?}: {saved}");
+    }
+}
+
+#[test]
+fn rate_limited_host_keeps_exiting_five_while_held() {
+    let server = MockServer::start(
+        ok("93.184.216.34"),
+        Reply {
+            status: 429,
+            body: "slow down",
+            delay: Duration::ZERO,
+        },
+    );
+    let state = temp_state_path();
+
+    let output = run_once(&server, &state, "@");
+    assert_eq!(output.status.code(), Some(5), "{}", logs(&output));
+    let attempts = server.update_requests().len();
+
+    // Held back by the rate limit, but still reported as not delivered
+    // rather than as rejected.
+    let output = run_once(&server, &state, "@");
+    assert_eq!(output.status.code(), Some(5), "{}", logs(&output));
+    assert!(logs(&output).contains("waiting out a retry delay"));
+    assert_eq!(server.update_requests().len(), attempts);
+}
+
+#[test]
+fn detection_failure_exits_three_without_updating() {
+    let server = MockServer::start(ok("<html>captive portal</html>"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+
+    let output = run_once(&server, &state, "@");
+    assert_eq!(output.status.code(), Some(3), "{}", logs(&output));
+    assert!(server.update_requests().is_empty());
+}
+
+#[test]
+fn password_never_appears_in_logs() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(BAD_PASSWORD_XML));
+    let state = temp_state_path();
+
+    let output = run_once(&server, &state, "@");
+    assert!(!logs(&output).contains(PASSWORD));
+    assert!(!std::fs::read_to_string(&state).unwrap().contains(PASSWORD));
+}
+
+/// A config file pointing at `server` with the given `[[domain]]` tables.
+fn config_file(server: &MockServer, state: &Path, domains: &str) -> PathBuf {
+    temp_file(
+        "toml",
+        &format!(
+            "ip_providers = [\"{}\"]\nendpoint = \"{}\"\nstate_file = \"{}\"\n\
+             [retry]\nbase_ms = 10\nmax_ms = 50\n{domains}",
+            server.url("/ip"),
+            server.url("/update"),
+            state.display()
+        ),
+    )
+}
+
+fn domain_table(name: &str, hosts: &str) -> String {
+    format!("[[domain]]\nname = \"{name}\"\npassword = \"{PASSWORD}\"\nhosts = [{hosts}]\n")
+}
+
+#[test]
+fn config_file_updates_every_domain() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+    let config = config_file(
+        &server,
+        &state,
+        &format!(
+            "{}\n{}interval_seconds = 600\n",
+            domain_table("example.com", "\"@\", \"www\""),
+            domain_table("example.org", "\"home\"")
+        ),
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_namecheap-ddns"))
+        .arg("--once")
+        .arg("--config")
+        .arg(&config)
+        .env_clear()
+        .env("RUST_LOG", "info")
+        .output()
+        .unwrap();
+    let logs = logs(&output);
+    assert_eq!(output.status.code(), Some(0), "{logs}");
+    assert!(
+        logs.contains("domain=example.com, hosts=[\"@\", \"www\"]"),
+        "{logs}"
+    );
+    assert!(logs.contains("interval=300s"), "{logs}");
+    assert!(
+        logs.contains("domain=example.org, hosts=[\"home\"]"),
+        "{logs}"
+    );
+    assert!(logs.contains("interval=600s"), "{logs}");
+
+    let mut updates = server.update_requests();
+    updates.sort();
+    assert_eq!(updates.len(), 3, "{updates:?}");
+    assert!(updates
+        .iter()
+        .any(|u| u.contains("domain=example.com") && u.contains("host=%40")));
+    assert!(updates
+        .iter()
+        .any(|u| u.contains("domain=example.com") && u.contains("host=www")));
+    assert!(updates
+        .iter()
+        .any(|u| u.contains("domain=example.org") && u.contains("host=home")));
+
+    let saved = std::fs::read_to_string(&state).unwrap();
+    for key in ["example.com/@", "example.com/www", "example.org/home"] {
+        assert!(saved.contains(&format!("\"{key}\"")), "{key}: {saved}");
+    }
+}
+
+#[test]
+fn config_flag_beats_nc_config_which_beats_the_environment() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+    let flag = config_file(&server, &state, &domain_table("flag.example", "\"@\""));
+    let var = config_file(&server, &state, &domain_table("var.example", "\"@\""));
+
+    let output = command(&server, &temp_state_path(), "@")
+        .arg("--config")
+        .arg(&flag)
+        .env("NC_CONFIG", &var)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(logs(&output).contains("ignoring NC_DOMAIN/NC_PASSWORD/NC_HOSTS"));
+
+    let output = command(&server, &temp_state_path(), "@")
+        .env("NC_CONFIG", &var)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(logs(&output).contains("ignoring NC_DOMAIN/NC_PASSWORD/NC_HOSTS"));
+
+    let output = run_once(&server, &temp_state_path(), "@");
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(!logs(&output).contains("ignoring NC_DOMAIN"));
+
+    let domains: Vec<_> = server
+        .update_requests()
+        .iter()
+        .map(|u| {
+            u.split('&')
+                .find_map(|p| p.strip_prefix("domain="))
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    assert_eq!(domains, vec!["flag.example", "var.example", "example.com"]);
+}
+
+#[test]
+fn config_file_errors_do_not_echo_the_password() {
+    let secret = "s3cretDDNSpass";
+    for contents in [
+        format!("[[domain]]\nname = \"example.com\"\npasword = \"{secret}\"\n"),
+        format!("[[domain]]\nname = \"example.com\"\npassword = \"{secret}\n"),
+        "[[domain]]\nname = \"example.com\"\npassword = 98765432123\n".to_string(),
+    ] {
+        let config = temp_file("toml", &contents);
+        let output = Command::new(env!("CARGO_BIN_EXE_namecheap-ddns"))
+            .arg("--once")
+            .arg("--config")
+            .arg(&config)
+            .env_clear()
+            .output()
+            .unwrap();
+
+        let logs = logs(&output);
+        assert_eq!(output.status.code(), Some(2), "{logs}");
+        assert!(logs.contains("line 3"), "{logs}");
+        assert!(!logs.contains(secret), "{logs}");
+        assert!(!logs.contains("98765432123"), "{logs}");
+    }
+}
+
+#[test]
+fn password_file_is_read_without_its_trailing_newline() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let secret = temp_file("secret", &format!("{PASSWORD}\n"));
+
+    let output = command(&server, &temp_state_path(), "@")
+        .env_remove("NC_PASSWORD")
+        .env("NC_PASSWORD_FILE", &secret)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 1);
+    assert!(
+        updates[0].contains(&format!("password={PASSWORD}&"))
+            || updates[0].ends_with(&format!("password={PASSWORD}")),
+        "{updates:?}"
+    );
+}
+
+#[test]
+fn file_variables_conflicts_and_read_errors_exit_two() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let secret = temp_file("secret", PASSWORD);
+
+    let output = command(&server, &temp_state_path(), "@")
+        .env("NC_PASSWORD_FILE", &secret)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2), "{}", logs(&output));
+    assert!(logs(&output).contains("both NC_PASSWORD and NC_PASSWORD_FILE are set"));
+
+    let output = command(&server, &temp_state_path(), "@")
+        .env_remove("NC_PASSWORD")
+        .env("NC_PASSWORD_FILE", "/nonexistent/namecheap-ddns/password")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2), "{}", logs(&output));
+    assert!(logs(&output).contains("NC_PASSWORD_FILE: failed to read"));
+
+    assert!(server.update_requests().is_empty());
+}
+
+#[test]
+fn nc_config_and_nc_once_honour_file_variants() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+    let config = config_file(&server, &state, &domain_table("file.example", "\"@\""));
+    let config_name = temp_file("path", &format!("{}\n", config.display()));
+    let once = temp_file("flag", "1\n");
+    let domain = temp_file("domain", "example.com\n");
+
+    // No --once: without NC_ONCE_FILE this would keep running.
+    let output = Command::new(env!("CARGO_BIN_EXE_namecheap-ddns"))
+        .env_clear()
+        .env("NC_ONCE_FILE", &once)
+        .env("NC_CONFIG_FILE", &config_name)
+        .env("NC_DOMAIN_FILE", &domain)
+        .output()
+        .unwrap();
+    let logs = logs(&output);
+    assert_eq!(output.status.code(), Some(0), "{logs}");
+    assert!(
+        logs.contains("ignoring NC_DOMAIN/NC_PASSWORD/NC_HOSTS"),
+        "{logs}"
+    );
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 1);
+    assert!(updates[0].contains("domain=file.example"), "{updates:?}");
+}
+
+#[test]
+fn invalid_configuration_exits_two() {
+    let output = Command::new(env!("CARGO_BIN_EXE_namecheap-ddns"))
+        .arg("--once")
+        .env_clear()
+        .env("NC_HOSTS", "bad host")
+        .env("NC_ENDPOINT", "not a url")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    let logs = logs(&output);
+    assert!(logs.contains("NC_DOMAIN"));
+    assert!(logs.contains("bad host"));
+    assert!(logs.contains("endpoint"));
+}
+
+fn quorum_server() -> MockServer {
+    MockServer::with_routes(vec![
+        ("/ip-a", ok("93.184.216.34")),
+        ("/ip-b", ok("93.184.216.34")),
+        ("/ip-c", ok("151.101.1.69")),
+        ("/update", ok(SUCCESS_XML)),
+    ])
+}
+
+fn quorum_providers(server: &MockServer) -> String {
+    ["/ip-a", "/ip-b", "/ip-c"]
+        .iter()
+        .map(|p| server.url(p))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[test]
+fn quorum_accepts_majority_and_logs_dissenters() {
+    let server = quorum_server();
+    let state = temp_state_path();
+
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", quorum_providers(&server))
+        .env("NC_DETECT_MODE", "quorum")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 1);
+    assert!(updates[0].contains("ip=93.184.216.34"));
+    assert!(logs(&output).contains("reported 151.101.1.69, disagreeing"));
+}
+
+#[test]
+fn quorum_not_reached_is_a_detection_failure() {
+    let server = quorum_server();
+    let state = temp_state_path();
+
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", quorum_providers(&server))
+        .env("NC_DETECT_MODE", "quorum")
+        .env("NC_QUORUM", "3")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "{}", logs(&output));
+    assert!(server.update_requests().is_empty());
+}
+
+#[test]
+fn race_returns_first_valid_answer_without_waiting_for_slow_providers() {
+    let server = MockServer::with_routes(vec![
+        (
+            "/ip-slow",
+            Reply {
+                status: 200,
+                body: "151.101.1.69",
+                delay: Duration::from_secs(5),
+            },
+        ),
+        ("/ip-broken", ok("not an ip")),
+        ("/ip-fast", ok("93.184.216.34")),
+        ("/update", ok(SUCCESS_XML)),
+    ]);
+    let state = temp_state_path();
+    let providers = ["/ip-slow", "/ip-broken", "/ip-fast"]
+        .iter()
+        .map(|p| server.url(p))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let started = Instant::now();
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", providers)
+        .env("NC_DETECT_MODE", "race")
+        .env("NC_RACE_STAGGER_MS", "50")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(started.elapsed() < Duration::from_secs(4));
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 1);
+    assert!(updates[0].contains("ip=93.184.216.34"));
+}
+
+#[test]
+fn private_addresses_are_rejected_unless_allowed() {
+    let server = MockServer::start(ok("100.64.12.34"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+
+    let output = run_once(&server, &state, "@");
+    assert_eq!(output.status.code(), Some(3), "{}", logs(&output));
+    assert!(logs(&output).contains("non-public address 100.64.12.34"));
+    assert!(server.update_requests().is_empty());
+
+    let output = command(&server, &state, "@")
+        .env("NC_ALLOW_PRIVATE_IP", "1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert_eq!(server.update_requests().len(), 1);
+}
+
+#[test]
+fn aaaa_hosts_are_updated_with_the_detected_ipv6() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let server6 = MockServer::bind("[::1]:0", vec![("/ip6", ok("2606:2800:220:1::1946\n"))]);
+    let state = temp_state_path();
+
+    let run = || {
+        command(&server, &state, "@,www")
+            .env("NC_AAAA_HOSTS", "@")
+            .env("NC_IP6_PROVIDERS", server6.url("/ip6"))
+            .output()
+            .unwrap()
+    };
+
+    let output = run();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(logs(&output).contains("Current IPv6: 2606:2800:220:1::1946"));
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 3);
+    assert_eq!(
+        updates
+            .iter()
+            .filter(|u| u.contains("ip=2606%3A2800%3A220%3A1%3A%3A1946"))
+            .count(),
+        1
+    );
+
+    let saved = std::fs::read_to_string(&state).unwrap();
+    assert!(saved.contains("example.com/@/AAAA"));
+    assert!(saved.contains("\"last_ip\": \"2606:2800:220:1::1946\""));
+
+    let output = run();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert_eq!(server.update_requests().len(), 3);
+}
+
+#[test]
+fn interface_provider_reads_the_local_address() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+
+    // Loopback is never public, so it is only used when explicitly allowed.
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", "interface:lo")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "{}", logs(&output));
+    assert!(logs(&output).contains("Interface lo has no public IPv4 address"));
+
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", "interface:lo")
+        .env("NC_ALLOW_PRIVATE_IP", "1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 1);
+    assert!(updates[0].contains("ip=127.0.0.1"));
+    assert!(server.requests.lock().unwrap().iter().all(|r| r != "/ip"));
+
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", "interface:does-not-exist0")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "{}", logs(&output));
+    assert!(logs(&output).contains("Interface does-not-exist0 not found"));
+}
+
+#[test]
+fn dns_providers_resolve_the_address_without_http() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    // The first query is lost and has to be retransmitted.
+    let nameserver = start_dns_responder([93, 184, 216, 34], 1);
+
+    for provider in ["dns:myip.opendns.com", "dns-txt:o-o.myaddr.l.google.com"] {
+        let state = temp_state_path();
+        let output = command(&server, &state, "@")
+            .env("NC_IP_PROVIDERS", format!("{provider}@{nameserver}"))
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    }
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 2);
+    assert!(updates.iter().all(|u| u.contains("ip=93.184.216.34")));
+    assert!(server.requests.lock().unwrap().iter().all(|r| r != "/ip"));
+}
+
+#[test]
+fn stun_provider_reads_the_xor_mapped_address() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let stun = start_stun_responder([93, 184, 216, 34], 1);
+    let state = temp_state_path();
+
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", format!("stun:{stun}"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 1);
+    assert!(updates[0].contains("ip=93.184.216.34"));
+    assert!(server.requests.lock().unwrap().iter().all(|r| r != "/ip"));
+}
+
+const UPNP_DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>
+        <controlURL>/ctl/L3F</controlURL>
+      </service>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+        <controlURL>/ctl/IPConn</controlURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#;
+
+const UPNP_EXTERNAL_IP: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/">
+  <s:Body>
+    <u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+      <NewExternalIPAddress>93.184.216.34</NewExternalIPAddress>
+    </u:GetExternalIPAddressResponse>
+  </s:Body>
+</s:Envelope>"#;
+
+#[test]
+fn router_providers_report_the_wan_address() {
+    let server = MockServer::with_routes(vec![
+        ("/rootDesc.xml", ok(UPNP_DESCRIPTION)),
+        ("/ctl/IPConn", ok(UPNP_EXTERNAL_IP)),
+        ("/update", ok(SUCCESS_XML)),
+    ]);
+    // The first two requests are lost; the retransmits get through.
+    let gateway = start_natpmp_responder([93, 184, 216, 34], 2);
+
+    let providers = [
+        format!("upnp:{}", server.url("/rootDesc.xml")),
+        format!("natpmp:{gateway}"),
+    ];
+    for provider in providers {
+        let state = temp_state_path();
+        let output = command(&server, &state, "@")
+            .env("NC_IP_PROVIDERS", provider)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    }
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 2);
+    assert!(updates.iter().all(|u| u.contains("ip=93.184.216.34")));
+    assert!(server
+        .requests
+        .lock()
+        .unwrap()
+        .contains(&"/ctl/IPConn".to_string()));
+}
+
+#[test]
+fn silent_router_falls_back_to_http_providers() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let state = temp_state_path();
+    // Nothing listens on this port once the socket is dropped.
+    let closed = UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let output = command(&server, &state, "@")
+        .env(
+            "NC_IP_PROVIDERS",
+            format!("natpmp:{closed},{}", server.url("/ip")),
+        )
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(logs(&output).contains(&format!("Provider natpmp:{closed} failed")));
+    assert_eq!(server.update_requests().len(), 1);
+}
+
+#[test]
+fn json_and_regex_extraction_read_structured_bodies() {
+    let server = MockServer::with_routes(vec![
+        ("/json", ok(r#"{"client":{"ip":"93.184.216.34","country":"US"}}"#)),
+        (
+            "/status.html",
+            ok("<html><td>WAN IP</td><td>93.184.216.34</td><td>LAN IP</td><td>192.168.1.1</td></html>"),
+        ),
+        ("/update", ok(SUCCESS_XML)),
+    ]);
+
+    let providers = [
+        format!("{}#json:/client/ip", server.url("/json")),
+        format!(
+            "{}#regex:WAN IP</td><td>([0-9.]+)",
+            server.url("/status.html")
+        ),
+    ];
+    for provider in providers {
+        let state = temp_state_path();
+        let output = command(&server, &state, "@")
+            .env("NC_IP_PROVIDERS", provider)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    }
+
+    let updates = server.update_requests();
+    assert_eq!(updates.len(), 2);
+    assert!(updates.iter().all(|u| u.contains("ip=93.184.216.34")));
+
+    let state = temp_state_path();
+    let output = command(&server, &state, "@")
+        .env(
+            "NC_IP_PROVIDERS",
+            format!("{}#json:/ip", server.url("/json")),
+        )
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "{}", logs(&output));
+    assert!(logs(&output).contains("no value at JSON pointer"));
+
+    let output = command(&server, &state, "@")
+        .env(
+            "NC_IP_PROVIDERS",
+            format!("{}#regex:([0-9.]+", server.url("/json")),
+        )
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2), "{}", logs(&output));
+    assert!(logs(&output).contains("invalid regex"));
+}
+
+#[test]
+fn exec_provider_uses_command_output_with_fallback_and_timeout() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+
+    let state = temp_state_path();
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", "exec:echo '93.184.216.34'")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert_eq!(server.update_requests().len(), 1);
+    assert!(server.requests.lock().unwrap().iter().all(|r| r != "/ip"));
+
+    // A failing command falls through to the next provider.
+    let state = temp_state_path();
+    let output = command(&server, &state, "@")
+        .env(
+            "NC_IP_PROVIDERS",
+            format!("exec:sh -c \"echo boom >&2; exit 1\",{}", server.url("/ip")),
+        )
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(logs(&output).contains("boom"));
+    assert_eq!(server.update_requests().len(), 2);
+
+    let state = temp_state_path();
+    let started = Instant::now();
+    let output = command(&server, &state, "@")
+        .env("NC_IP_PROVIDERS", "exec:sleep 30")
+        .env("NC_EXEC_TIMEOUT_SECONDS", "1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "{}", logs(&output));
+    assert!(logs(&output).contains("timed out"));
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn failing_providers_are_demoted_and_quarantined() {
+    let server = MockServer::with_routes(vec![
+        ("/ip", ok("93.184.216.34")),
+        ("/update", ok(SUCCESS_XML)),
+    ]);
+    // Provider health lives next to the state file, so use a private dir.
+    let dir = temp_state_path().with_extension("d");
+    std::fs::create_dir_all(&dir).unwrap();
+    let state = dir.join("state.json");
+    let broken = server.url("/ip-broken");
+    let run = |providers: String| {
+        command(&server, &state, "@")
+            .env("NC_IP_PROVIDERS", providers)
+            .env("NC_PERSIST_PROVIDER_HEALTH", "1")
+            .env("LOG_STYLE", "json")
+            .output()
+            .unwrap()
+    };
+    let broken_requests = || {
+        server
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| *r == "/ip-broken")
+            .count()
+    };
+
+    let providers = format!("{broken},{}", server.url("/ip"));
+    let output = run(providers.clone());
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert_eq!(broken_requests(), 1);
+
+    // The working provider is now tried first, so the broken one is skipped.
+    let output = run(providers);
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    let reordered = format!("IPv4 provider order is now {}, {broken}", server.url("/ip"));
+    assert!(logs(&output).contains(&reordered), "{}", logs(&output));
+    // The scores stay on the same line, so JSON logs are one object per line.
+    for line in logs(&output).lines() {
+        let record: serde_json::Value = serde_json::from_str(line).expect(line);
+        let msg = record["msg"].as_str().unwrap_or_default();
+        if msg.contains("provider order is now") {
+            assert!(
+                msg.contains("/ip: score=") && msg.contains("/ip-broken: score="),
+                "{msg}"
+            );
+        }
+    }
+    assert_eq!(broken_requests(), 1);
+
+    for _ in 0..2 {
+        assert_eq!(run(broken.clone()).status.code(), Some(3));
+    }
+    let output = run(broken.clone());
+    assert!(logs(&output).contains("quarantined"), "{}", logs(&output));
+
+    let health = std::fs::read_to_string(dir.join("provider_health.json")).unwrap();
+    assert!(health.contains("\"quarantined_until\": 1"), "{health}");
+    assert!(health.contains("\"consecutive_failures\": 4"), "{health}");
+}
+
+#[test]
+fn published_record_mismatch_triggers_update_despite_cache() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let outdated = start_dns_responder([151, 101, 1, 69], 0);
+    let current = start_dns_responder([93, 184, 216, 34], 0);
+    let state = temp_state_path();
+    let run = |nameserver: &str| {
+        command(&server, &state, "@")
+            .env("NC_VERIFY_DNS", "1")
+            .env("NC_DNS_SERVERS", nameserver)
+            .output()
+            .unwrap()
+    };
+
+    assert_eq!(run(&outdated).status.code(), Some(0));
+    assert_eq!(server.update_requests().len(), 1);
+
+    // The state file says the record is current, but DNS disagrees.
+    let output = run(&outdated);
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(logs(&output).contains("Published A record for example.com is [151.101.1.69]"));
+    assert_eq!(server.update_requests().len(), 2);
+
+    let output = run(&current);
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert_eq!(server.update_requests().len(), 2);
+}
+
+#[test]
+fn propagation_is_verified_or_reported_after_the_deadline() {
+    let server = MockServer::start(ok("93.184.216.34"), ok(SUCCESS_XML));
+    let current = start_dns_responder([93, 184, 216, 34], 0);
+    let outdated = start_dns_responder([151, 101, 1, 69], 0);
+    let run = |nameserver: &str| {
+        command(&server, &temp_state_path(), "@,www")
+            .env("NC_PROPAGATION_TIMEOUT_SECONDS", "1")
+            .env("NC_DNS_SERVERS", nameserver)
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&current);
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(logs(&output).contains("Verified A record for example.com = 93.184.216.34"));
+    assert!(logs(&output).contains("Verified A record for www.example.com"));
+
+    let started = Instant::now();
+    let output = run(&outdated);
+    assert_eq!(output.status.code(), Some(0), "{}", logs(&output));
+    assert!(logs(&output).contains(
+        "Update of A record for www.example.com to 93.184.216.34 not visible on the nameservers after 1s (still publishing [151.101.1.69])"
+    ));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
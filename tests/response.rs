@@ -0,0 +1,148 @@
+use namecheap_ddns::{
+    classify_response, parse_namecheap_response, ErrorClass, RetryPolicy, UpdateError,
+};
+use std::net::IpAddr;
+
+const SUCCESS_XML: &str = r#"<?xml version="1.0" encoding="utf-16"?>
+<interface-response>
+  <Command>SETDNSHOST</Command>
+  <Language>eng</Language>
+  <IP>203.0.113.7</IP>
+  <ErrCount>0</ErrCount>
+  <ResponseCount>0</ResponseCount>
+  <Done>true</Done>
+  <debug><![CDATA[]]></debug>
+</interface-response>"#;
+
+const BAD_PASSWORD_XML: &str = r#"<?xml version="1.0" encoding="utf-16"?>
+<interface-response>
+  <Command>SETDNSHOST</Command>
+  <Language>eng</Language>
+  <ErrCount>1</ErrCount>
+  <errors>
+    <Err1>Passwords do not match</Err1>
+  </errors>
+  <ResponseCount>1</ResponseCount>
+  <responses>
+    <response>
+      <ResponseNumber>304156</ResponseNumber>
+      <ResponseString>Validation error; invalid ; password</ResponseString>
+    </response>
+  </responses>
+  <Done>true</Done>
+  <debug><![CDATA[trace id 42]]></debug>
+</interface-response>"#;
+
+#[test]
+fn parses_success_response() {
+    let response = parse_namecheap_response(SUCCESS_XML).unwrap();
+
+    assert!(response.is_success());
+    assert_eq!(response.command.as_deref(), Some("SETDNSHOST"));
+    assert_eq!(response.language.as_deref(), Some("eng"));
+    assert_eq!(response.ip, Some("203.0.113.7".parse::<IpAddr>().unwrap()));
+    assert_eq!(response.response_count, 0);
+    assert!(response.done);
+    assert_eq!(response.debug, None);
+}
+
+#[test]
+fn parses_errors_and_responses() {
+    let response = parse_namecheap_response(BAD_PASSWORD_XML).unwrap();
+
+    assert!(!response.is_success());
+    assert_eq!(response.err_count, 1);
+    let errors: Vec<(u32, &str)> = response
+        .errors
+        .iter()
+        .map(|e| (e.number, e.message.as_str()))
+        .collect();
+    assert_eq!(errors, vec![(1, "Passwords do not match")]);
+    assert_eq!(response.response_count, 1);
+    assert_eq!(response.codes(), vec![304156]);
+    assert_eq!(
+        response.messages(),
+        vec![
+            "Passwords do not match",
+            "Validation error; invalid ; password"
+        ]
+    );
+    assert_eq!(response.debug.as_deref(), Some("trace id 42"));
+}
+
+#[test]
+fn malformed_xml_is_an_error() {
+    let err = parse_namecheap_response("<interface-response><ErrCount>1</Err").unwrap_err();
+    assert!(matches!(err, UpdateError::Malformed(_)));
+}
+
+#[test]
+fn bodies_without_interface_response_or_err_count_are_malformed() {
+    let bodies = [
+        "",
+        "down for maintenance",
+        "<html><body>Proxy error</body></html>",
+        "<interface-response><Command>SETDNSHOST</Command><Done>true</Done></interface-response>",
+        "<interface-response><ErrCount>none</ErrCount></interface-response>",
+    ];
+
+    for body in bodies {
+        let err = parse_namecheap_response(body).unwrap_err();
+        assert!(matches!(err, UpdateError::Malformed(_)), "{body:?}");
+        assert_eq!(err.class(), ErrorClass::ServerError, "{body:?}");
+    }
+}
+
+fn error_xml(err: &str, response_number: u32, response_string: &str) -> String {
+    format!(
+        "<interface-response><ErrCount>1</ErrCount><errors><Err1>{err}</Err1></errors>\
+         <ResponseCount>1</ResponseCount><responses><response>\
+         <ResponseNumber>{response_number}</ResponseNumber>\
+         <ResponseString>{response_string}</ResponseString>\
+         </response></responses><Done>true</Done></interface-response>"
+    )
+}
+
+#[test]
+fn classifies_known_errors() {
+    let cases = [
+        (BAD_PASSWORD_XML.to_string(), ErrorClass::Auth),
+        (
+            error_xml(
+                "Domain name not found",
+                316153,
+                "Validation error; not found ; domain name(s)",
+            ),
+            ErrorClass::UnknownDomain,
+        ),
+        (
+            error_xml(
+                "No Records updated. A record not Found;",
+                380091,
+                "No updates; A record not Found;",
+            ),
+            ErrorClass::RecordMissing,
+        ),
+        (
+            error_xml("Something new", 999999, "Nobody knows"),
+            ErrorClass::Unknown,
+        ),
+    ];
+
+    for (xml, expected) in cases {
+        let response = parse_namecheap_response(&xml).unwrap();
+        assert_eq!(classify_response(&response), expected, "{xml}");
+    }
+}
+
+#[test]
+fn permanent_errors_are_not_retried_every_cycle() {
+    assert!(matches!(
+        ErrorClass::Auth.retry_policy(),
+        RetryPolicy::After(_)
+    ));
+    assert_eq!(
+        ErrorClass::ServerError.retry_policy(),
+        RetryPolicy::NextCycle
+    );
+}
@@ -0,0 +1,37 @@
+use namecheap_ddns::{wait_for_propagation, Family};
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn propagation_wait_stops_at_the_deadline_despite_silent_nameservers() {
+    // Bound but never answering, so every query would run its full timeout.
+    let silent: Vec<UdpSocket> = (0..2)
+        .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+        .collect();
+    let servers: Vec<String> = silent
+        .iter()
+        .map(|s| s.local_addr().unwrap().to_string())
+        .collect();
+    let names: Vec<String> = ["example.com", "www.example.com", "home.example.com"]
+        .iter()
+        .map(|n| n.to_string())
+        .collect();
+
+    let started = Instant::now();
+    let records = wait_for_propagation(
+        &names,
+        Family::V4,
+        "93.184.216.34",
+        &servers,
+        Duration::from_secs(1),
+    )
+    .await;
+
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        started.elapsed()
+    );
+    assert_eq!(records.len(), 3);
+    assert!(records.iter().all(|r| r.verified_after.is_none()));
+}